@@ -9,10 +9,16 @@
 extern crate libc;
 
 pub use libc::sched_yield;
+#[cfg(feature = "emulate_sbrk")]
+pub use emulated_sbrk::sbrk;
 
+#[cfg(not(feature = "emulate_sbrk"))]
 extern {
     /// Change the data segment. See `man sbrk`.
     pub fn sbrk(ptr: libc::intptr_t) -> *const libc::c_void;
+}
+
+extern {
     /// Write a buffer to a file descriptor.
     fn write(fd: libc::c_int, buff: *const libc::c_void, size: libc::size_t) -> libc::ssize_t;
 }
@@ -24,6 +30,133 @@ pub fn log(s: &str) -> libc::ssize_t {
     unsafe { write(2, s.as_ptr() as *const libc::c_void, s.len()) }
 }
 
+/// Read a monotonic clock, in nanoseconds.
+///
+/// The starting point is arbitrary, so only differences between readings are meaningful.
+#[cfg(feature = "syscall_timing")]
+#[allow(cast_sign_loss)]
+pub fn monotonic_nanos() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts);
+    }
+
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+/// Emulation of `sbrk` on top of `mmap`, for platforms without `sbrk`.
+///
+/// A large region is reserved through `mmap`, and the emulated program break is moved within it,
+/// so consecutive breaks are contiguous like with a real `sbrk`. When the region is exhausted, a
+/// new one is reserved, and the break jumps to it (which `ralloc` handles like another user
+/// moving the break).
+#[cfg(feature = "emulate_sbrk")]
+pub mod emulated_sbrk {
+    use libc;
+    use core::{cmp, ptr};
+
+    /// The size of a reserved region.
+    #[cfg(target_pointer_width = "64")]
+    const REGION_SIZE: usize = 1 << 30;
+    /// The size of a reserved region.
+    #[cfg(not(target_pointer_width = "64"))]
+    const REGION_SIZE: usize = 1 << 24;
+
+    /// The start of the current region (or 0, if no region is reserved yet).
+    static mut START: usize = 0;
+    /// The current emulated program break.
+    static mut BRK: usize = 0;
+    /// The end of the current region.
+    static mut END: usize = 0;
+
+    /// Reserve a new region of at least `size` bytes, and move the break to its start.
+    unsafe fn reserve(size: usize) -> Result<(), ()> {
+        let size = cmp::max(size, REGION_SIZE);
+
+        let region = libc::mmap(ptr::null_mut(), size, libc::PROT_READ | libc::PROT_WRITE,
+                                libc::MAP_PRIVATE | libc::MAP_ANON, -1, 0);
+        if region == libc::MAP_FAILED {
+            return Err(());
+        }
+
+        // The unused tail of the old region is abandoned.
+        START = region as usize;
+        BRK = START;
+        END = START + size;
+
+        Ok(())
+    }
+
+    /// Change the emulated data segment. See `man sbrk`.
+    ///
+    /// Like `sbrk`, this returns the old break on success, and `-1` on failure. The break cannot
+    /// be shrunk below the start of the current region.
+    ///
+    /// # Safety
+    ///
+    /// This is not thread-safe, so calls must be synchronized (`ralloc` does so through its BRK
+    /// lock).
+    pub unsafe fn sbrk(n: libc::intptr_t) -> *const libc::c_void {
+        /// The error value.
+        const ERR: *const libc::c_void = !0 as *const libc::c_void;
+
+        if n < 0 {
+            let n = n.wrapping_neg() as usize;
+
+            if BRK - START < n {
+                return ERR;
+            }
+
+            let old = BRK;
+            BRK -= n;
+            old as *const libc::c_void
+        } else {
+            let n = n as usize;
+
+            // Reserve a region if there is none, or the current one is exhausted.
+            if (START == 0 || END - BRK < n) && reserve(n).is_err() {
+                return ERR;
+            }
+
+            let old = BRK;
+            BRK += n;
+            old as *const libc::c_void
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn test_emulated_sbrk() {
+            unsafe {
+                let start = sbrk(0) as usize;
+                assert!(start != !0);
+
+                // Growing is contiguous within the region.
+                assert_eq!(sbrk(100) as usize, start);
+                assert_eq!(sbrk(50) as usize, start + 100);
+                assert_eq!(sbrk(0) as usize, start + 150);
+
+                // The memory is usable.
+                *(start as *mut u8).offset(149) = 42;
+
+                // Shrink it back down.
+                assert_eq!(sbrk(-150) as usize, start + 150);
+                assert_eq!(sbrk(0) as usize, start);
+
+                // It cannot be shrunk below the region.
+                assert_eq!(sbrk(-1) as usize, !0);
+            }
+        }
+    }
+}
+
 /// Thread destructors for Linux.
 #[cfg(target_os = "linux")]
 pub mod thread_destructor {
@@ -90,3 +223,25 @@ pub mod thread_destructor {
         _tlv_atexit(dtor, t);
     }
 }
+
+/// Get the size of a cache line of the L1 data cache, in bytes.
+///
+/// Returns 0 if it is unknown.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+#[allow(cast_sign_loss)]
+pub fn cache_line_size() -> usize {
+    /// The `sysconf` name of the L1 data cache line size (a glibc extension).
+    const _SC_LEVEL1_DCACHE_LINESIZE: libc::c_int = 190;
+
+    let size = unsafe { libc::sysconf(_SC_LEVEL1_DCACHE_LINESIZE) };
+    if size > 0 { size as usize } else { 0 }
+}
+
+/// Get the size of a cache line of the L1 data cache, in bytes.
+///
+/// Returns 0 if it is unknown, which is always the case without glibc, since the `sysconf` name
+/// used is glibc-specific.
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+pub fn cache_line_size() -> usize {
+    0
+}
@@ -4,27 +4,202 @@
 
 use prelude::*;
 
-use core::{mem, ops};
+use core::{cmp, mem, ops, ptr};
+#[cfg(feature = "tls")]
+use core::sync::atomic::{self, AtomicUsize};
+#[cfg(feature = "oom_unwind")]
+use core::sync::atomic::AtomicBool;
 
-use {brk, sync};
+use {block, brk, bump, event, fail, sync, sys};
+use event::AllocEvent;
 use bookkeeper::{self, Bookkeeper, Allocator};
 
 #[cfg(feature = "tls")]
 use tls;
+#[cfg(feature = "debug_tools")]
+use trace;
+#[cfg(feature = "stats")]
+use stats;
+#[cfg(feature = "sanitizer")]
+use sanitizer;
+#[cfg(feature = "quarantine")]
+use quarantine;
 
 /// Alias for the wrapper type of the thread-local variable holding the local allocator.
 #[cfg(feature = "tls")]
 type ThreadLocalAllocator = MoveCell<Option<LazyInit<fn() -> LocalAllocator, LocalAllocator>>>;
 
+/// The default limit of the thread-local allocator, in bytes.
+#[cfg(feature = "tls")]
+const DEFAULT_THREAD_CACHE_LIMIT: usize = 256 * 1024;
+
+/// The maximal number of bytes held by a thread-local allocator.
+///
+/// When the local allocator holds more than this, memory is moved to the global allocator.
+#[cfg(feature = "tls")]
+static THREAD_CACHE_LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_THREAD_CACHE_LIMIT);
+
+/// The lazily initialized global allocator.
+type GlobalLazy = LazyInit<fn() -> GlobalAllocator, GlobalAllocator>;
+
+/// The size of the red zone placed after every allocation.
+#[cfg(feature = "redzone")]
+const REDZONE_SIZE: usize = 8;
+/// The size of the red zone placed after every allocation.
+#[cfg(not(feature = "redzone"))]
+const REDZONE_SIZE: usize = 0;
+/// The canary value filling the red zones.
+#[cfg(feature = "redzone")]
+const REDZONE_CANARY: u8 = 0xAB;
+
 /// The global default allocator.
 // TODO: Remove these filthy function pointers.
-static GLOBAL_ALLOCATOR: sync::Mutex<LazyInit<fn() -> GlobalAllocator, GlobalAllocator>> =
-    sync::Mutex::new(LazyInit::new(global_init));
+static GLOBAL_ALLOCATOR: sync::Mutex<GlobalLazy> = sync::Mutex::new(LazyInit::new(global_init));
 #[cfg(feature = "tls")]
 tls! {
     /// The thread-local allocator.
     static THREAD_ALLOCATOR: ThreadLocalAllocator = MoveCell::new(Some(LazyInit::new(local_init)));
 }
+#[cfg(feature = "oom_unwind")]
+tls! {
+    /// Does the current thread hold the lock of the global allocator?
+    static GLOBAL_LOCK_HELD: MoveCell<bool> = MoveCell::new(false);
+}
+/// Was an operation on the global allocator interrupted by the OOM handler?
+#[cfg(feature = "oom_unwind")]
+static GLOBAL_INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// A lock of the global allocator.
+#[cfg(not(feature = "oom_unwind"))]
+type GlobalGuard = sync::MutexGuard<'static, GlobalLazy>;
+
+/// A lock of the global allocator.
+///
+/// In contrast to a plain mutex guard, this knows if the lock was released by
+/// [`release_global_lock`](fn.release_global_lock.html), in which case it won't release it again.
+#[cfg(feature = "oom_unwind")]
+struct GlobalGuard {
+    /// The inner guard.
+    ///
+    /// This is only `None` while dropping.
+    inner: Option<sync::MutexGuard<'static, GlobalLazy>>,
+}
+
+#[cfg(feature = "oom_unwind")]
+impl ops::Deref for GlobalGuard {
+    type Target = GlobalLazy;
+
+    fn deref(&self) -> &GlobalLazy {
+        self.inner.as_ref().unwrap()
+    }
+}
+
+#[cfg(feature = "oom_unwind")]
+impl ops::DerefMut for GlobalGuard {
+    fn deref_mut(&mut self) -> &mut GlobalLazy {
+        self.inner.as_mut().unwrap()
+    }
+}
+
+#[cfg(feature = "oom_unwind")]
+impl Drop for GlobalGuard {
+    fn drop(&mut self) {
+        let guard = self.inner.take().unwrap();
+
+        if GLOBAL_LOCK_HELD.with(|held| held.replace(false)) {
+            // Release the lock.
+            drop(guard);
+        } else {
+            // The lock was already released by the OOM path, and might be held by someone else
+            // by now.
+            mem::forget(guard);
+        }
+    }
+}
+
+/// Lock the global allocator.
+#[inline]
+#[cfg(not(feature = "oom_unwind"))]
+fn lock_global() -> GlobalGuard {
+    GLOBAL_ALLOCATOR.lock()
+}
+
+/// Lock the global allocator.
+///
+/// If the previous operation was interrupted by the OOM handler, the global allocator will be
+/// recovered.
+#[inline]
+#[cfg(feature = "oom_unwind")]
+fn lock_global() -> GlobalGuard {
+    let mut guard = GlobalGuard {
+        inner: Some(GLOBAL_ALLOCATOR.lock()),
+    };
+    GLOBAL_LOCK_HELD.with(|held| held.replace(true));
+
+    if GLOBAL_INTERRUPTED.swap(false, atomic::Ordering::SeqCst) {
+        guard.get().recover();
+    }
+
+    guard
+}
+
+/// Release the lock of the global allocator, if the current thread holds it.
+///
+/// This is called by the OOM path before the OOM handler, so that the handler (or whatever it
+/// unwinds to) can use the allocator again. The interrupted operation is abandoned, and the global
+/// allocator is recovered the next time it is locked.
+#[cfg(feature = "oom_unwind")]
+pub fn release_global_lock() {
+    if GLOBAL_LOCK_HELD.with(|held| held.replace(false)) {
+        GLOBAL_INTERRUPTED.store(true, atomic::Ordering::SeqCst);
+
+        // The guard holding the lock notices that it has been released, and won't release it
+        // again.
+        unsafe { GLOBAL_ALLOCATOR.force_unlock(); }
+    }
+}
+
+/// A guard moving the thread-local allocator back into its cell, when dropped.
+///
+/// The local allocator is moved out while in use, so reentrant calls fall back to the global
+/// allocator. Putting it back in a destructor makes sure that it is restored, even if the OOM
+/// handler unwinds.
+#[cfg(feature = "tls")]
+struct PutBack<'a> {
+    /// The thread-local cell.
+    cell: &'a ThreadLocalAllocator,
+    /// The moved out allocator.
+    ///
+    /// This is only `None` while dropping.
+    alloc: Option<LazyInit<fn() -> LocalAllocator, LocalAllocator>>,
+}
+
+#[cfg(feature = "tls")]
+impl<'a> PutBack<'a> {
+    /// Get the local allocator, initializing it if needed.
+    #[inline]
+    fn get(&mut self) -> &mut LocalAllocator {
+        self.alloc.as_mut().unwrap().get()
+    }
+}
+
+#[cfg(feature = "tls")]
+impl<'a> Drop for PutBack<'a> {
+    #[inline]
+    fn drop(&mut self) {
+        let mut alloc = self.alloc.take();
+
+        // The operation might have been interrupted by the OOM handler.
+        #[cfg(feature = "oom_unwind")]
+        {
+            if let Some(local) = alloc.as_mut().and_then(|x| x.get_initialized()) {
+                local.recover();
+            }
+        }
+
+        self.cell.replace(alloc);
+    }
+}
 
 /// Initialize the global allocator.
 fn global_init() -> GlobalAllocator {
@@ -62,7 +237,7 @@ fn local_init() -> LocalAllocator {
         let alloc = alloc.replace(None).expect("Thread-local allocator is already freed.");
 
         // Lock the global allocator.
-        let mut global_alloc = GLOBAL_ALLOCATOR.lock();
+        let mut global_alloc = lock_global();
         let global_alloc = global_alloc.get();
 
         // TODO: we know this is sorted, so we could abuse that fact to faster insertion in the
@@ -72,8 +247,7 @@ fn local_init() -> LocalAllocator {
     }
 
     // The initial acquired segment.
-    let initial_segment = GLOBAL_ALLOCATOR
-        .lock()
+    let initial_segment = lock_global()
         .get()
         .alloc(4 * bookkeeper::EXTRA_ELEMENTS * mem::size_of::<Block>(), mem::align_of::<Block>());
 
@@ -106,23 +280,22 @@ macro_rules! get_allocator {
         #[cfg(feature = "tls")]
         {
             THREAD_ALLOCATOR.with(|thread_alloc| {
-                if let Some(mut thread_alloc_original) = thread_alloc.replace(None) {
-                    let res = {
-                        // Call the closure involved.
-                        let $v = thread_alloc_original.get();
-                        $b
+                if let Some(thread_alloc_original) = thread_alloc.replace(None) {
+                    // The original allocator is put back when the guard is dropped.
+                    let mut guard = PutBack {
+                        cell: thread_alloc,
+                        alloc: Some(thread_alloc_original),
                     };
 
-                    // Put back the original allocator.
-                    thread_alloc.replace(Some(thread_alloc_original));
-
-                    res
+                    // Call the closure involved.
+                    let $v = guard.get();
+                    $b
                 } else {
                     // The local allocator seems to have been deinitialized, for this reason we fallback to
                     // the global allocator.
 
                     // Lock the global allocator.
-                    let mut guard = GLOBAL_ALLOCATOR.lock();
+                    let mut guard = lock_global();
 
                     // Call the block in question.
                     let $v = guard.get();
@@ -135,7 +308,7 @@ macro_rules! get_allocator {
         #[cfg(not(feature = "tls"))]
         {
             // Lock the global allocator.
-            let mut guard = GLOBAL_ALLOCATOR.lock();
+            let mut guard = lock_global();
 
             // Call the block in question.
             let $v = guard.get();
@@ -183,16 +356,33 @@ impl Allocator for GlobalAllocator {
         // Obtain what you need.
         let (alignment_block, res, excessive) = brk::get(size, align);
 
-        // Add it to the list. This will not change the order, since the pointer is higher than all
-        // the previous blocks (BRK extends the data segment). Although, it is worth noting that
-        // the stack is higher than the program break.
-        self.push(alignment_block);
-        self.push(excessive);
+        // Add it to the list. The segment is not necessarily contiguous with the previous one, as
+        // some other code might have moved the program break in the meantime. This is fine:
+        // Blocks are only merged when they are adjacent, so the new segment will simply live on
+        // its own.
+        self.add_fresh(alignment_block);
+        self.add_fresh(excessive);
 
         res
     }
 }
 
+impl GlobalAllocator {
+    /// Add a block of fresh memory from the program break to the pool.
+    ///
+    /// Usually, the pointer is higher than all the previous blocks (BRK extends the data segment),
+    /// so the block can simply be pushed. A region donated through `add_region` might lie above
+    /// the program break, though (as the stack does), in which case the block is freed into its
+    /// place, keeping the pool sorted.
+    fn add_fresh(&mut self, block: Block) {
+        if self.blocks().last().map_or(true, |x| x < &block) {
+            self.push(block);
+        } else {
+            self.free(block);
+        }
+    }
+}
+
 /// A local allocator.
 ///
 /// This acquires memory from the upstream (global) allocator, which is protected by a `Mutex`.
@@ -211,18 +401,419 @@ impl Allocator for LocalAllocator {
     fn alloc_fresh(&mut self, size: usize, align: usize) -> Block {
         // Get the block from the global allocator. Please note that we cannot canonicalize `size`,
         // due to freeing excessive blocks would change the order.
-        GLOBAL_ALLOCATOR.lock().get().alloc(size, align)
+        lock_global().get().alloc(size, align)
+    }
+
+    fn on_new_memory(&mut self) {
+        let limit = THREAD_CACHE_LIMIT.load(atomic::Ordering::Relaxed);
+
+        if self.total_bytes() > limit {
+            // Lock the global allocator.
+            let mut global_alloc = lock_global();
+            let global_alloc = global_alloc.get();
+
+            // Move the top blocks to the global allocator until we are below the limit.
+            while self.total_bytes() > limit {
+                let block = self.pop().expect("The pool is empty, yet holds memory.");
+                global_alloc.free(block);
+            }
+        }
+    }
+}
+
+/// Set the maximal number of bytes held by the thread-local allocators.
+///
+/// Whenever memory is freed into a thread-local allocator holding more than `bytes` bytes, memory
+/// is moved to the global allocator. This takes effect on the next free. The default limit is 256
+/// KiB.
+#[inline]
+#[cfg(feature = "tls")]
+pub fn set_thread_cache_limit(bytes: usize) {
+    THREAD_CACHE_LIMIT.store(bytes, atomic::Ordering::Relaxed);
+}
+
+/// An iterator over the free blocks of the global allocator.
+///
+/// This yields the address and size of every free block, in ascending order. The global allocator
+/// is locked until the iterator is dropped, so the blocks cannot change while iterating, but any
+/// allocation in the meantime will deadlock.
+#[cfg(feature = "debug_tools")]
+pub struct FreeBlockIter {
+    /// The lock of the global allocator.
+    guard: GlobalGuard,
+    /// The index of the next block in the pool.
+    ind: usize,
+}
+
+#[cfg(feature = "debug_tools")]
+impl Iterator for FreeBlockIter {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        loop {
+            let (ptr, size) = match self.guard.get().blocks().get(self.ind) {
+                Some(block) => (*Pointer::from(block.empty_left()) as usize, block.size()),
+                None => return None,
+            };
+            self.ind += 1;
+
+            // Skip the empty blocks.
+            if size != 0 {
+                return Some((ptr, size));
+            }
+        }
+    }
+}
+
+/// Iterate over the free blocks of the global allocator.
+///
+/// This is meant for external diagnostic tools (e.g. to calculate fragmentation metrics). See
+/// [`FreeBlockIter`](struct.FreeBlockIter.html) for details. Note that memory held by the
+/// thread-local allocators is not included.
+#[inline]
+#[cfg(feature = "debug_tools")]
+pub fn free_blocks() -> FreeBlockIter {
+    FreeBlockIter {
+        guard: lock_global(),
+        ind: 0,
+    }
+}
+
+/// A summary of the fragmentation of the free memory.
+#[derive(Clone, Copy, Debug)]
+#[cfg(feature = "debug_tools")]
+pub struct FragmentationReport {
+    /// The total number of free bytes.
+    pub total_free: usize,
+    /// The size of the largest free block.
+    pub largest_free: usize,
+    /// The number of free blocks.
+    pub free_blocks: usize,
+    /// The fragmentation ratio, `1 - largest_free / total_free`.
+    ///
+    /// This is 0 when all the free memory is in one block (or there is none), and approaches 1 as
+    /// the free memory is split into many small blocks.
+    pub ratio: f64,
+}
+
+#[cfg(feature = "debug_tools")]
+impl FragmentationReport {
+    /// Summarize some free blocks, given as `(address, size)` pairs.
+    #[allow(cast_precision_loss)]
+    fn from_blocks<I: Iterator<Item = (usize, usize)>>(blocks: I) -> FragmentationReport {
+        let mut res = FragmentationReport {
+            total_free: 0,
+            largest_free: 0,
+            free_blocks: 0,
+            ratio: 0.0,
+        };
+
+        for (_, size) in blocks {
+            res.total_free += size;
+            res.largest_free = cmp::max(res.largest_free, size);
+            res.free_blocks += 1;
+        }
+
+        if res.total_free != 0 {
+            res.ratio = 1.0 - res.largest_free as f64 / res.total_free as f64;
+        }
+
+        res
     }
 }
 
+/// Summarize the fragmentation of the global allocator.
+///
+/// This iterates over the free blocks under the lock (see [`free_blocks`](fn.free_blocks.html)),
+/// so the report is consistent, and does not allocate. Like `free_blocks`, memory held by the
+/// thread-local allocators is not included.
+#[inline]
+#[cfg(feature = "debug_tools")]
+pub fn fragmentation() -> FragmentationReport {
+    FragmentationReport::from_blocks(free_blocks())
+}
+
+/// Count some free blocks, given as `(address, size)` pairs, by their size class.
+#[cfg(feature = "debug_tools")]
+fn count_classes<I: Iterator<Item = (usize, usize)>>(blocks: I) -> [usize; block::NUM_CLASSES] {
+    let mut res = [0; block::NUM_CLASSES];
+
+    for (_, size) in blocks {
+        res[Block::size_class(size)] += 1;
+    }
+
+    res
+}
+
+/// Count the free blocks of the global allocator by their size class.
+///
+/// Entry `n` is the number of free blocks in size class `n` (see `Block::size_class`). The last
+/// class covers every block larger than the classes below it. Like
+/// [`fragmentation`](fn.fragmentation.html), the blocks are counted under the lock, and memory
+/// held by the thread-local allocators is not included.
+#[inline]
+#[cfg(feature = "debug_tools")]
+pub fn free_counts() -> [usize; block::NUM_CLASSES] {
+    count_classes(free_blocks())
+}
+
+/// Was `ptr` allocated by ralloc?
+///
+/// This checks whether `ptr` lies within the memory acquired by ralloc from the program break,
+/// which is a cheap range check. It might give false positives for memory placed on the break by
+/// others in between ralloc's segments, but it is never false for a live ralloc allocation (of
+/// non-zero size) from the program break. Memory donated through
+/// [`add_region`](fn.add_region.html) is not included.
+#[inline]
+pub fn owns(ptr: *const u8) -> bool {
+    brk::in_heap(ptr)
+}
+
+/// Donate a memory region to the allocator.
+///
+/// The region is placed in the pool of the global allocator as free memory, from which later
+/// allocations can be served. It is never given back to the OS (see
+/// [`drain_to_os`](fn.drain_to_os.html)), since only memory at the top of the program break is
+/// released. Regions can be added at any time, and they need not be contiguous.
+///
+/// # Safety
+///
+/// The region must be valid, unused, and outlive every allocation made from it. It must not be
+/// part of the program break. It may lie above the program break, however, in which case memory,
+/// which is later acquired from the break, is placed below it.
+#[inline]
+pub unsafe fn add_region(ptr: *mut u8, size: usize) {
+    lock_global().get().free(Block::from_raw_parts(Pointer::new(ptr), size));
+}
+
+/// Get the size of the largest allocation, which can be served without growing the heap.
+///
+/// This is the size of the largest free block of the global allocator, read under its lock.
+/// Larger allocations are not necessarily doomed, since the program break can often be extended,
+/// but whether it can is not known before trying. Memory held by the thread-local allocators is
+/// not included.
+#[inline]
+pub fn max_alloc_size() -> usize {
+    lock_global().get().largest_free()
+}
+
+/// Give the free memory at the top of the program break back to the OS.
+///
+/// The quarantined blocks (with the `quarantine` feature) are released, and the cache of the
+/// current thread's local allocator is moved to the global allocator first. Then,
+/// if the topmost free block ends at the program break, the break is moved back below it. The
+/// return value is the number of bytes released.
+///
+/// This is meant for tests and short-lived tools, which want to return memory at exit. Only free
+/// memory is ever released, so live allocations are unaffected, but they (as well as SBRK usage
+/// outside of ralloc) can keep the break from moving back.
+pub fn drain_to_os() -> usize {
+    // Release the quarantine, which might hold the top of the heap.
+    #[cfg(feature = "quarantine")]
+    quarantine::flush(|block| get_allocator!(|alloc| alloc.free(block)));
+
+    // Move the local cache to the global allocator.
+    #[cfg(feature = "tls")]
+    THREAD_ALLOCATOR.with(|thread_alloc| {
+        if let Some(thread_alloc_original) = thread_alloc.replace(None) {
+            let mut guard = PutBack {
+                cell: thread_alloc,
+                alloc: Some(thread_alloc_original),
+            };
+            let local = guard.get();
+
+            let mut global_alloc = lock_global();
+            let global_alloc = global_alloc.get();
+
+            while let Some(block) = local.pop() {
+                global_alloc.free(block);
+            }
+        }
+    });
+
+    let mut global_alloc = lock_global();
+    let global_alloc = global_alloc.get();
+
+    // Get the current program break.
+    let end = match unsafe { sys::sbrk(0) } {
+        Ok(end) => end,
+        Err(()) => return 0,
+    };
+
+    // Blocks in the pool are never adjacent, so only the top block can be released.
+    if let Some(block) = global_alloc.pop_top_at(unsafe { Pointer::new(end) }) {
+        let size = block.size();
+
+        if unsafe { sys::shrink_brk(end, size) }.is_ok() {
+            return size;
+        }
+
+        // Someone else moved the break in the meantime, so we put the block back.
+        global_alloc.push(block);
+    }
+
+    0
+}
+
+/// Place the red zone after the buffer of `size` bytes at `ptr`.
+///
+/// The red zone is `REDZONE_SIZE` bytes filled with a canary, which is checked when the buffer is
+/// freed, catching (linear) buffer overflows. Without the `redzone` feature, this does nothing.
+#[inline]
+#[allow(unused_variables)]
+unsafe fn write_redzone(ptr: *mut u8, size: usize) {
+    #[cfg(feature = "redzone")]
+    Block::from_raw_parts(Pointer::new(ptr.offset(size as isize)), REDZONE_SIZE)
+        .fill(REDZONE_CANARY);
+}
+
+/// Check the red zone after the buffer of `size` bytes at `ptr`.
+///
+/// # Panics
+///
+/// This will panic if the canary is overwritten.
+#[inline]
+#[allow(unused_variables)]
+unsafe fn check_redzone(ptr: *mut u8, size: usize) {
+    // The red zone is kept poisoned, so it is unpoisoned to be read. The buffer is given to the
+    // allocator afterwards, which maintains the poisoning from there.
+    #[cfg(all(feature = "redzone", feature = "sanitizer"))]
+    sanitizer::allocated(ptr.offset(size as isize), REDZONE_SIZE, 0);
+
+    #[cfg(feature = "redzone")]
+    for i in 0..REDZONE_SIZE {
+        assert!(*ptr.offset((size + i) as isize) == REDZONE_CANARY,
+                "The red zone of the buffer 0x{:x}[{}] is corrupted (buffer overflow?).",
+                ptr as usize, size);
+    }
+}
+
+/// Add the red zone to the size of a buffer.
+///
+/// No buffer can be this large, if the sum overflows, so the OOM handler is called then.
+#[inline]
+fn with_redzone(size: usize) -> usize {
+    size.checked_add(REDZONE_SIZE).unwrap_or_else(|| fail::oom())
+}
+
 /// Allocate a block of memory.
 ///
+/// With the `redzone` feature, a red zone is placed after the buffer, and checked on free. This
+/// requires that the buffer is freed whole, i.e. with its original size.
+///
 /// # Errors
 ///
 /// The OOM handler handles out-of-memory conditions.
 #[inline]
 pub fn alloc(size: usize, align: usize) -> *mut u8 {
-    get_allocator!(|alloc| *Pointer::from(alloc.alloc(size, align)))
+    if let Some(ptr) = bump::alloc(size, align) {
+        event::emit(AllocEvent::Alloc { ptr: ptr, size: size, align: align });
+        return ptr;
+    }
+
+    // The size is checked before the allocator is locked, since an overflow ends in the OOM
+    // handler.
+    let total = with_redzone(size);
+    let ptr = get_allocator!(|alloc| *Pointer::from(alloc.alloc(total, align)));
+    unsafe { write_redzone(ptr, size); }
+
+    #[cfg(feature = "sanitizer")]
+    sanitizer::allocated(ptr, size, REDZONE_SIZE);
+
+    #[cfg(feature = "debug_tools")]
+    trace::record(trace::Op::Alloc, ptr, size);
+
+    // Account it as untagged.
+    #[cfg(feature = "stats")]
+    stats::record_alloc(0, size);
+
+    event::emit(AllocEvent::Alloc { ptr: ptr, size: size, align: align });
+
+    ptr
+}
+
+/// Allocate several buffers contiguously.
+///
+/// One buffer is allocated, and carved into sub-buffers of the given `sizes`, each aligned to
+/// `align` and placed right after the previous one (up to the alignment padding). The pointers
+/// are written to `ptrs`, one per size.
+///
+/// The return value is the total size, which is to be passed to [`free`](fn.free.html) along
+/// with the first pointer, when all the sub-buffers are freed at once.
+///
+/// # Panics
+///
+/// This will panic if `ptrs` is shorter than `sizes`.
+///
+/// # Errors
+///
+/// The OOM handler handles out-of-memory conditions, including a total size, which overflows.
+#[allow(cast_possible_wrap)]
+pub fn alloc_contiguous(sizes: &[usize], align: usize, ptrs: &mut [*mut u8]) -> usize {
+    assert!(ptrs.len() >= sizes.len(), "Not enough room for the pointers.");
+
+    // Calculate the total size, including the padding between the sub-buffers.
+    let total = sizes.iter().fold(Some(0), |end, &size| {
+        end.and_then(|end| block::checked_align_up(end, align))
+            .and_then(|start| start.checked_add(size))
+    }).unwrap_or_else(|| fail::oom());
+
+    let ptr = alloc(total, align);
+
+    let mut end = 0;
+    for (&size, out) in sizes.iter().zip(ptrs.iter_mut()) {
+        let start = block::align_up(end, align);
+        // The offset is bounded by the total size, so neither this nor the sum overflows.
+        *out = unsafe { ptr.offset(start as isize) };
+        end = start + size;
+    }
+
+    total
+}
+
+/// Allocate a block of memory aligned to the cache line size.
+///
+/// This is [`alloc`](fn.alloc.html) with the alignment set to the cache line size of the platform
+/// (64 bytes, if it is unknown), which avoids false sharing with neighboring allocations.
+///
+/// # Errors
+///
+/// The OOM handler handles out-of-memory conditions.
+#[inline]
+pub fn alloc_cache_aligned(size: usize) -> *mut u8 {
+    alloc(size, sys::cache_line_size())
+}
+
+/// Allocate a block of memory with a tag.
+///
+/// This is like [`alloc`](fn.alloc.html), but the allocation is accounted to `tag` (see
+/// `live_bytes_by_tag`), which can be used to attribute memory usage to subsystems. There is no
+/// room for the tag in the allocation, so the buffer must be freed through
+/// [`free_tagged`](fn.free_tagged.html) with the same tag.
+///
+/// Tag 0 is the one used by plain `alloc`.
+///
+/// # Errors
+///
+/// The OOM handler handles out-of-memory conditions.
+#[inline]
+#[cfg(feature = "stats")]
+pub fn alloc_tagged(size: usize, align: usize, tag: u32) -> *mut u8 {
+    let total = with_redzone(size);
+    let ptr = get_allocator!(|alloc| *Pointer::from(alloc.alloc(total, align)));
+    unsafe { write_redzone(ptr, size); }
+
+    #[cfg(feature = "sanitizer")]
+    sanitizer::allocated(ptr, size, REDZONE_SIZE);
+
+    #[cfg(feature = "debug_tools")]
+    trace::record(trace::Op::Alloc, ptr, size);
+
+    stats::record_alloc(tag, size);
+
+    event::emit(AllocEvent::Alloc { ptr: ptr, size: size, align: align });
+
+    ptr
 }
 
 /// Free a buffer.
@@ -230,6 +821,10 @@ pub fn alloc(size: usize, align: usize) -> *mut u8 {
 /// Note that this do not have to be a buffer allocated through ralloc. The only requirement is
 /// that it is not used after the free.
 ///
+/// With the `quarantine` feature enabled, the buffer is held back for a while, before it can be
+/// handed out again. Combined with the `sanitizer` feature, this makes stale accesses to it more
+/// likely to be caught.
+///
 /// # Important!
 ///
 /// You should only allocate buffers allocated through `ralloc`. Anything else is considered
@@ -247,7 +842,123 @@ pub fn alloc(size: usize, align: usize) -> *mut u8 {
 /// Secondly, freeing an used buffer can introduce use-after-free.
 #[inline]
 pub unsafe fn free(ptr: *mut u8, size: usize) {
-    get_allocator!(|alloc| alloc.free(Block::from_raw_parts(Pointer::new(ptr), size)))
+    free_with_tag(ptr, size, 0);
+
+    event::emit(AllocEvent::Free { ptr: ptr, size: size });
+}
+
+/// Free a buffer, accounting it to `tag`.
+///
+/// Buffers in the bump region are freed with it, so they are skipped. No event is emitted.
+/// Without the `stats` feature, the tag is ignored.
+#[allow(unused_variables)]
+unsafe fn free_with_tag(ptr: *mut u8, size: usize, tag: u32) {
+    if bump::contains(ptr) {
+        return;
+    }
+
+    #[cfg(feature = "debug_tools")]
+    trace::record(trace::Op::Free, ptr, size);
+
+    #[cfg(feature = "stats")]
+    stats::record_free(tag, size);
+
+    check_redzone(ptr, size);
+
+    release(Block::from_raw_parts(Pointer::new(ptr), with_redzone(size)));
+}
+
+/// Give a freed block back to the allocator.
+///
+/// With the `quarantine` feature enabled, the block is put in quarantine instead, and is only
+/// given back once enough blocks have been freed after it. With the `sanitizer` feature, the
+/// block is poisoned.
+#[inline]
+fn release(block: Block) {
+    #[cfg(feature = "sanitizer")]
+    sanitizer::freed(*Pointer::from(block.empty_left()), block.size());
+
+    #[cfg(feature = "quarantine")]
+    quarantine::delay(block, |block| get_allocator!(|alloc| alloc.free(block)));
+
+    #[cfg(not(feature = "quarantine"))]
+    get_allocator!(|alloc| alloc.free(block));
+}
+
+/// Reallocate a block through the allocator.
+///
+/// With the `quarantine` feature enabled, the memory freed by moving or shrinking the block is put
+/// in quarantine, like any freed buffer. For this, the block is moved here, rather than by the
+/// allocator.
+fn realloc_block(block: Block, new_size: usize, align: usize) -> Block {
+    #[cfg(feature = "quarantine")]
+    {
+        let res = if block.aligned_to(align) {
+            realloc_block_inplace(block, new_size)
+        } else {
+            Err(block)
+        };
+
+        return match res {
+            Ok(block) => block,
+            Err(block) => {
+                let mut res = get_allocator!(|alloc| alloc.alloc(new_size, align));
+                block.copy_prefix_to(&mut res, cmp::min(block.size(), new_size));
+                release(block);
+
+                res
+            },
+        };
+    }
+
+    #[cfg(not(feature = "quarantine"))]
+    return get_allocator!(|alloc| alloc.realloc(block, new_size, align));
+}
+
+/// Resize a block inplace through the allocator.
+///
+/// With the `quarantine` feature enabled, the tail cut off by shrinking the block is put in
+/// quarantine, like any freed buffer.
+fn realloc_block_inplace(block: Block, new_size: usize) -> Result<Block, Block> {
+    #[cfg(feature = "quarantine")]
+    {
+        if new_size <= block.size() {
+            let (res, tail) = block.split(new_size);
+            release(tail);
+
+            return Ok(res);
+        }
+    }
+
+    get_allocator!(|alloc| alloc.realloc_inplace(block, new_size))
+}
+
+/// Free a buffer allocated with a tag.
+///
+/// This is like [`free`](fn.free.html), but the buffer is accounted to `tag`, which should be
+/// the one it was allocated with through [`alloc_tagged`](fn.alloc_tagged.html).
+///
+/// # Safety
+///
+/// See [`free`](fn.free.html).
+#[inline]
+#[cfg(feature = "stats")]
+pub unsafe fn free_tagged(ptr: *mut u8, size: usize, tag: u32) {
+    free_with_tag(ptr, size, tag);
+
+    event::emit(AllocEvent::Free { ptr: ptr, size: size });
+}
+
+/// Get the number of bytes used by the allocator for its own bookkeeping.
+///
+/// ralloc keeps no headers or footers, so this is the memory backing the pools of free blocks,
+/// plus the red zones of the live allocations (with the `redzone` feature). Alignment padding is
+/// not included, since it is kept as free memory, rather than being lost. The count assumes that
+/// every buffer is freed whole.
+#[inline]
+#[cfg(feature = "stats")]
+pub fn overhead_bytes() -> usize {
+    stats::live_allocs().saturating_mul(REDZONE_SIZE).saturating_add(bookkeeper::pool_bytes())
 }
 
 /// Reallocate memory.
@@ -255,6 +966,21 @@ pub unsafe fn free(ptr: *mut u8, size: usize) {
 /// Reallocate the buffer starting at `ptr` with size `old_size`, to a buffer starting at the
 /// returned pointer with size `size`.
 ///
+/// The returned buffer is aligned to `align`, even if it had to be moved. In contrast to libc's
+/// `realloc`, this means that over-aligned buffers stay over-aligned, given that the original
+/// alignment is passed. `align` may also be stricter than the alignment the buffer was allocated
+/// with, in which case the buffer is moved, if it is not suitably aligned already.
+///
+/// With TLS enabled, the reallocation is done entirely by the thread-local allocator: if the
+/// buffer has to be moved, the new buffer is taken from the thread's cache, and the old one is
+/// returned to it. As with `free`, memory beyond the cache limit (see `set_thread_cache_limit`)
+/// then moves on to the global allocator, which is otherwise only involved if the cache has no
+/// fitting block.
+///
+/// Like libc's `realloc`, a null `ptr` is treated as an empty buffer, so this is equivalent to
+/// [`alloc`](fn.alloc.html) (`old_size` is ignored). Reallocating to size 0 frees the buffer, and
+/// returns the (non-null) pointer of an empty buffer, which can be freed with size 0.
+///
 /// # Important!
 ///
 /// You should only reallocate buffers allocated through `ralloc`. Anything else is considered
@@ -270,34 +996,491 @@ pub unsafe fn free(ptr: *mut u8, size: usize) {
 /// this is marked unsafe.
 #[inline]
 pub unsafe fn realloc(ptr: *mut u8, old_size: usize, size: usize, align: usize) -> *mut u8 {
-    get_allocator!(|alloc| {
-        *Pointer::from(alloc.realloc(
-            Block::from_raw_parts(Pointer::new(ptr), old_size),
-            size,
-            align
-        ))
-    })
+    realloc_with_tag(ptr, old_size, size, align, 0)
+}
+
+/// Reallocate a buffer allocated with a tag.
+///
+/// This is like [`realloc`](fn.realloc.html), but the buffer stays accounted to `tag`, which
+/// should be the one it was allocated with through [`alloc_tagged`](fn.alloc_tagged.html).
+///
+/// # Errors
+///
+/// The OOM handler handles out-of-memory conditions.
+///
+/// # Safety
+///
+/// See [`realloc`](fn.realloc.html).
+#[inline]
+#[cfg(feature = "stats")]
+pub unsafe fn realloc_tagged(ptr: *mut u8, old_size: usize, size: usize, align: usize, tag: u32)
+                             -> *mut u8 {
+    realloc_with_tag(ptr, old_size, size, align, tag)
+}
+
+/// Reallocate memory, accounting the buffer to `tag`.
+///
+/// Without the `stats` feature, the tag is ignored.
+#[allow(unused_variables)]
+unsafe fn realloc_with_tag(ptr: *mut u8, old_size: usize, size: usize, align: usize, tag: u32)
+                           -> *mut u8 {
+    // There is no block to look up for a null pointer.
+    if ptr.is_null() {
+        #[cfg(feature = "stats")]
+        return alloc_tagged(size, align, tag);
+        #[cfg(not(feature = "stats"))]
+        return alloc(size, align);
+    }
+
+    // In bump mode, the new buffer is taken from the bump region. A buffer allocated before is
+    // freed as usual. Tagged buffers stay out of the region, like those of `alloc_tagged`, so
+    // they stay accounted to their tag.
+    if tag == 0 && bump::is_active() {
+        let new = bump::alloc(size, align).expect("Bump mode is active, yet there is no region.");
+        ptr::copy_nonoverlapping(ptr, new, cmp::min(old_size, size));
+        free_with_tag(ptr, old_size, tag);
+
+        event::emit(AllocEvent::Realloc { old: ptr, new: new, size: size });
+
+        return new;
+    }
+
+    check_redzone(ptr, old_size);
+
+    let new = *Pointer::from(realloc_block(
+        Block::from_raw_parts(Pointer::new(ptr), with_redzone(old_size)),
+        with_redzone(size),
+        align
+    ));
+    write_redzone(new, size);
+
+    #[cfg(feature = "sanitizer")]
+    sanitizer::allocated(new, size, REDZONE_SIZE);
+
+    #[cfg(feature = "debug_tools")]
+    trace::record(trace::Op::Realloc, new, size);
+
+    #[cfg(feature = "stats")]
+    {
+        stats::record_free(tag, old_size);
+        stats::record_alloc(tag, size);
+    }
+
+    event::emit(AllocEvent::Realloc { old: ptr, new: new, size: size });
+
+    new
+}
+
+/// Reallocate memory, reporting whether the buffer was moved.
+///
+/// This is like [`realloc`](fn.realloc.html), but the returned pointer is paired with whether it
+/// differs from `ptr`, i.e. whether the buffer was moved. Pointers into the old buffer must be
+/// fixed up if it was.
+///
+/// # Errors
+///
+/// The OOM handler handles out-of-memory conditions, so the buffer is never left behind.
+///
+/// # Safety
+///
+/// See [`realloc`](fn.realloc.html).
+#[inline]
+pub unsafe fn realloc_tracked(ptr: *mut u8, old_size: usize, size: usize, align: usize)
+                              -> (*mut u8, bool) {
+    let new = realloc(ptr, old_size, size, align);
+
+    (new, new != ptr)
 }
 
 /// Try to reallocate the buffer _inplace_.
 ///
-/// In case of success, return the new buffer's size. On failure, return the old size.
+/// The buffer is never moved, so this never copies, and its cost does not depend on its size.
+/// In case of success, the buffer has been resized to `size`. On failure, `Err(())` is returned,
+/// and the buffer is left intact with its old size.
 ///
-/// This can be used to shrink (truncate) a buffer as well.
+/// This can be used to shrink (truncate) a buffer as well, which never fails.
 ///
 /// # Safety
 ///
 /// Due to being able to shrink (and thus free) the buffer, this is marked unsafe.
 #[inline]
 pub unsafe fn realloc_inplace(ptr: *mut u8, old_size: usize, size: usize) -> Result<(), ()> {
-    get_allocator!(|alloc| {
-        if alloc.realloc_inplace(
-            Block::from_raw_parts(Pointer::new(ptr), old_size),
-            size
-        ).is_ok() {
-            Ok(())
-        } else {
-            Err(())
+    // Buffers in the bump region can only shrink, which leaves the rest unused.
+    if bump::contains(ptr) {
+        if size > old_size {
+            return Err(());
+        }
+
+        event::emit(AllocEvent::Realloc { old: ptr, new: ptr, size: size });
+
+        return Ok(());
+    }
+
+    check_redzone(ptr, old_size);
+
+    let res = if realloc_block_inplace(
+        Block::from_raw_parts(Pointer::new(ptr), with_redzone(old_size)),
+        with_redzone(size)
+    ).is_ok() {
+        write_redzone(ptr, size);
+
+        #[cfg(feature = "sanitizer")]
+        sanitizer::allocated(ptr, size, REDZONE_SIZE);
+
+        Ok(())
+    } else {
+        // The buffer is left intact, so its red zone is poisoned again.
+        #[cfg(feature = "sanitizer")]
+        sanitizer::allocated(ptr, old_size, REDZONE_SIZE);
+
+        Err(())
+    };
+
+    // The allocator is released before the accounting, since it might call back.
+    #[cfg(feature = "stats")]
+    {
+        if res.is_ok() {
+            stats::record_free(0, old_size);
+            stats::record_alloc(0, size);
+        }
+    }
+
+    if res.is_ok() {
+        event::emit(AllocEvent::Realloc { old: ptr, new: ptr, size: size });
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use core::ptr;
+
+    use {block, sys};
+
+    #[test]
+    fn test_alloc_cache_aligned() {
+        let ptr = alloc_cache_aligned(24);
+        assert_eq!(ptr as usize % sys::cache_line_size(), 0);
+
+        unsafe {
+            *ptr.offset(23) = 1;
+            free(ptr, 24);
         }
-    })
+    }
+
+    #[test]
+    fn test_owns() {
+        let ptr = alloc(16, 1);
+
+        assert!(owns(ptr));
+        assert!(owns(unsafe { ptr.offset(15) }));
+        assert!(!owns(&ptr as *const _ as *const u8));
+
+        unsafe { free(ptr, 16); }
+    }
+
+    #[test]
+    fn test_add_region() {
+        /// The regions given to the allocator. These must outlive the test, since they stay in
+        /// the pool.
+        static mut REGION_A: [u8; 2048] = [0; 2048];
+        static mut REGION_B: [u8; 2048] = [0; 2048];
+
+        let (a, b) = unsafe { (REGION_A.as_mut_ptr() as usize, REGION_B.as_mut_ptr() as usize) };
+        let within = |ptr: usize| (ptr >= a && ptr < a + 2048) || (ptr >= b && ptr < b + 2048);
+
+        unsafe {
+            add_region(a as *mut u8, 2048);
+            add_region(b as *mut u8, 2048);
+        }
+
+        // The regions are free memory of the global allocator now. Other threads might allocate
+        // from them in the meantime, so we only check that some of it is still free.
+        let mut guard = lock_global();
+        let free = guard.get().blocks().iter()
+            .filter(|block| within(*Pointer::from(block.empty_left()) as usize))
+            .fold(0, |acc, block| acc + block.size());
+        assert!(free > 0 && free <= 2 * 2048);
+    }
+
+    #[test]
+    fn test_add_region_above_brk() {
+        // The stack lies above the program break, so a region of it is donated to an allocator of
+        // our own, which then has to grow the heap below it.
+        let mut region = [0u8; 256];
+        let mut alloc = global_init();
+
+        unsafe {
+            alloc.free(Block::from_raw_parts(Pointer::new(region.as_mut_ptr()), 256));
+        }
+
+        let block = alloc.alloc(4096, 1);
+        assert!(alloc.blocks().windows(2).all(|x| x[0] < x[1]));
+        alloc.free(block);
+        assert!(alloc.blocks().windows(2).all(|x| x[0] < x[1]));
+
+        // The memory of the allocator is leaked, as the region dies with the stack frame.
+        mem::forget(alloc);
+    }
+
+    #[test]
+    fn test_alloc_contiguous() {
+        let mut ptrs = [ptr::null_mut(); 3];
+        let total = alloc_contiguous(&[3, 16, 5], 8, &mut ptrs);
+
+        assert_eq!(total, 8 + 16 + 5);
+        assert_eq!(ptrs[0] as usize % 8, 0);
+        assert_eq!(ptrs[1] as usize, ptrs[0] as usize + 8);
+        assert_eq!(ptrs[2] as usize, ptrs[0] as usize + 24);
+
+        unsafe {
+            *ptrs[2].offset(4) = 1;
+            free(ptrs[0], total);
+        }
+    }
+
+    #[test]
+    fn test_realloc_null() {
+        unsafe {
+            let ptr = realloc(ptr::null_mut(), 0, 16, 8);
+            assert!(!ptr.is_null());
+            assert_eq!(ptr as usize % 8, 0);
+
+            // The buffer is usable.
+            for i in 0..16 {
+                *ptr.offset(i) = i as u8;
+            }
+            assert_eq!(*ptr.offset(15), 15);
+
+            free(ptr, 16);
+        }
+    }
+
+    #[test]
+    fn test_realloc_stricter_align() {
+        unsafe {
+            let ptr = alloc(100, 1);
+            for i in 0..100 {
+                *ptr.offset(i) = i as u8;
+            }
+
+            let ptr = realloc(ptr, 100, 200, 256);
+            assert_eq!(ptr as usize % 256, 0);
+            for i in 0..100 {
+                assert_eq!(*ptr.offset(i), i as u8);
+            }
+
+            free(ptr, 200);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "quarantine")]
+    fn test_quarantine() {
+        unsafe {
+            let ptr = alloc(48, 8);
+            free(ptr, 48);
+
+            // The freed buffer is in quarantine, so it is not handed out again.
+            let new = alloc(48, 8);
+            assert!(new != ptr);
+
+            free(new, 48);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "quarantine")]
+    fn test_quarantine_realloc() {
+        unsafe {
+            let ptr = alloc(48, 8);
+            assert!(realloc_inplace(ptr, 48, 16).is_ok());
+
+            // The tail is in quarantine, so the buffer cannot grow back into it.
+            assert!(realloc_inplace(ptr, 16, 48).is_err());
+
+            // Move the buffer by asking for an alignment it does not have.
+            let align = (ptr as usize & (ptr as usize).wrapping_neg()) * 2;
+            let new = realloc(ptr, 16, 16, align);
+            assert!(new != ptr);
+
+            // The old buffer is in quarantine, so it is not handed out again.
+            let other = alloc(16, 8);
+            assert!(other != ptr);
+
+            free(other, 16);
+            free(new, 16);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "quarantine"))]
+    fn test_realloc_inplace() {
+        unsafe {
+            let ptr = alloc(64, 8);
+            *ptr = 42;
+
+            // Shrinking never fails...
+            assert!(realloc_inplace(ptr, 64, 16).is_ok());
+            // ... and growing into the freed space again succeeds.
+            assert!(realloc_inplace(ptr, 16, 64).is_ok());
+            *ptr.offset(63) = 7;
+
+            assert_eq!(*ptr, 42);
+            free(ptr, 64);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "redzone"))]
+    fn test_realloc_inplace_blocked() {
+        unsafe {
+            // Split one allocation into two adjacent buffers.
+            let a = alloc(64, 8);
+            let b = a.offset(32);
+            *a = 1;
+            *b = 2;
+
+            // `a` cannot grow into `b`, and is left intact.
+            assert!(realloc_inplace(a, 32, 48).is_err());
+            assert_eq!(*a, 1);
+            assert_eq!(*b, 2);
+
+            free(a, 32);
+            free(b, 32);
+        }
+    }
+
+    #[test]
+    fn test_realloc_tracked_inplace() {
+        unsafe {
+            let ptr = alloc(64, 1);
+            *ptr = 42;
+
+            // Shrinking is done inplace.
+            let (new, moved) = realloc_tracked(ptr, 64, 16, 1);
+            assert!(!moved);
+            assert_eq!(new, ptr);
+            assert_eq!(*new, 42);
+
+            free(new, 16);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "redzone"))]
+    fn test_realloc_tracked_moved() {
+        unsafe {
+            // Split one allocation into two adjacent buffers, so `a` cannot grow inplace.
+            let a = alloc(64, 1);
+            let b = a.offset(32);
+            *a = 42;
+
+            let (new, moved) = realloc_tracked(a, 32, 48, 1);
+            assert!(moved);
+            assert!(new != a);
+            assert_eq!(*new, 42);
+
+            free(new, 48);
+            free(b, 32);
+        }
+    }
+
+    #[test]
+    fn test_realloc_zero() {
+        unsafe {
+            let ptr = alloc(16, 1);
+            *ptr = 42;
+
+            // Shrinking to zero frees the buffer, but gives a non-null pointer back...
+            let empty = realloc(ptr, 16, 0, 1);
+            assert!(!empty.is_null());
+
+            // ... which can be freed with size 0.
+            free(empty, 0);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "debug_tools")]
+    fn test_fragmentation_report() {
+        let blocks = [(0x1000, 16), (0x2000, 64), (0x3000, 16)];
+        let report = FragmentationReport::from_blocks(blocks.iter().cloned());
+
+        assert_eq!(report.total_free, 96);
+        assert_eq!(report.largest_free, 64);
+        assert_eq!(report.free_blocks, 3);
+        assert!((report.ratio - 1.0 / 3.0).abs() < 1e-9);
+
+        let report = FragmentationReport::from_blocks([(0x1000, 32)].iter().cloned());
+        assert_eq!(report.ratio, 0.0);
+
+        let report = FragmentationReport::from_blocks([].iter().cloned());
+        assert_eq!(report.total_free, 0);
+        assert_eq!(report.ratio, 0.0);
+    }
+
+    #[test]
+    #[cfg(feature = "debug_tools")]
+    fn test_free_counts() {
+        let blocks = [(0x1000, 8), (0x2000, 16), (0x3000, 17), (0x4000, 64), (0x5000, 1 << 30)];
+        let counts = count_classes(blocks.iter().cloned());
+
+        assert_eq!(counts[0], 2);
+        assert_eq!(counts[1], 1);
+        assert_eq!(counts[2], 1);
+        assert_eq!(counts[block::NUM_CLASSES - 1], 1);
+        assert_eq!(counts.iter().fold(0, |acc, x| acc + x), 5);
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(feature = "redzone")]
+    fn test_redzone_overflow() {
+        unsafe {
+            let ptr = alloc(16, 1);
+
+            // Write one byte past the buffer.
+            *ptr.offset(16) = 0;
+            free(ptr, 16);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "debug_tools")]
+    fn test_free_blocks() {
+        /// The memory given to the allocator. This must outlive the test, since it stays in the
+        /// pool.
+        static mut ARENA: [u8; 64] = [0; 64];
+
+        let (a, b) = unsafe {
+            (ARENA.as_mut_ptr() as usize, ARENA.as_mut_ptr().offset(32) as usize)
+        };
+
+        // Free the two (non-adjacent) blocks with the same lock as the iterator, so no one else
+        // can allocate them in the meantime.
+        let mut guard = lock_global();
+        unsafe {
+            guard.get().free(Block::from_raw_parts(Pointer::new(a as *mut u8), 16));
+            guard.get().free(Block::from_raw_parts(Pointer::new(b as *mut u8), 8));
+        }
+
+        let mut found = [false; 2];
+        for (ptr, size) in (FreeBlockIter { guard: guard, ind: 0 }) {
+            assert!(size != 0, "Empty block yielded.");
+
+            if ptr == a {
+                assert_eq!(size, 16);
+                found[0] = true;
+            } else if ptr == b {
+                assert_eq!(size, 8);
+                found[1] = true;
+            }
+        }
+
+        assert_eq!(found, [true, true]);
+    }
 }
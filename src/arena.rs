@@ -0,0 +1,229 @@
+//! Bump allocation arenas.
+//!
+//! An arena hands out memory by bumping a pointer through a chain of chunks, and frees everything
+//! allocated from it at once, when it is reset.
+
+use prelude::*;
+
+use core::{mem, ptr, cmp};
+
+use allocator;
+
+/// The number of chunks given back by dropped arenas, on this thread.
+///
+/// This is used to check that the chunks are not leaked.
+#[cfg(test)]
+#[thread_local]
+pub static mut RELEASED_CHUNKS: usize = 0;
+
+/// The header of a chunk.
+///
+/// This is placed at the start of every chunk, and is followed by the memory bumped from.
+struct Chunk {
+    /// The next chunk in the chain, or null.
+    next: *mut Chunk,
+    /// The size of the chunk, including the header.
+    size: usize,
+}
+
+/// A bump allocation arena.
+///
+/// Allocation simply bumps a pointer through the current chunk. When the chunk is full, the next
+/// chunk in the chain is used, acquiring a new one from the global allocator if necessary.
+///
+/// Individual allocations cannot be freed. Instead, [`reset`](#method.reset) frees every
+/// allocation at once in O(1), by rewinding the bump pointer to the start of the first chunk. The
+/// chunks are kept for reuse, and are only given back when the arena is dropped.
+pub struct Arena {
+    /// The first chunk of the chain, or null, if no chunk was acquired yet.
+    first: *mut Chunk,
+    /// The chunk currently bumped from, or null.
+    current: *mut Chunk,
+    /// The unused part of the current chunk.
+    free: Block,
+    /// The minimum size of a chunk.
+    chunk_size: usize,
+}
+
+impl Arena {
+    /// Create a new arena, acquiring chunks of (at least) `chunk_size` bytes.
+    ///
+    /// No memory is acquired before the first allocation.
+    pub fn new(chunk_size: usize) -> Arena {
+        Arena {
+            first: ptr::null_mut(),
+            current: ptr::null_mut(),
+            free: Block::empty(Pointer::empty()),
+            chunk_size: chunk_size,
+        }
+    }
+
+    /// Allocate `size` bytes aligned to `align`.
+    ///
+    /// The buffer is valid until the arena is reset or dropped.
+    ///
+    /// # Errors
+    ///
+    /// The OOM handler handles out-of-memory conditions.
+    pub fn alloc(&mut self, size: usize, align: usize) -> *mut u8 {
+        if let Some(ptr) = self.bump(size, align) {
+            return ptr;
+        }
+
+        self.next_chunk(size, align);
+        self.bump(size, align).expect("The new chunk is too small for the allocation.")
+    }
+
+    /// Free every allocation, rewinding the arena to the start of its first chunk.
+    ///
+    /// The chunks are kept, so subsequent allocations reuse the same memory.
+    pub fn reset(&mut self) {
+        self.current = self.first;
+        self.free = unsafe { Arena::body(self.first) };
+    }
+
+    /// Does `ptr` point into a chunk of this arena?
+    ///
+    /// This walks the chain of chunks, so it takes time linear in their number.
+    pub fn contains(&self, ptr: *const u8) -> bool {
+        let ptr = ptr as usize;
+        let mut chunk = self.first;
+
+        while !chunk.is_null() {
+            unsafe {
+                if chunk as usize <= ptr && ptr < chunk as usize + (*chunk).size {
+                    return true;
+                }
+
+                chunk = (*chunk).next;
+            }
+        }
+
+        false
+    }
+
+    /// Try to bump-allocate from the current chunk.
+    fn bump(&mut self, size: usize, align: usize) -> Option<*mut u8> {
+        if let Some((mut aligner, mut rest)) = self.free.align(align) {
+            if rest.size() >= size {
+                let (res, free) = rest.split(size);
+                self.free = free;
+
+                return Some(*Pointer::from(res));
+            }
+
+            // Put the unused part back together.
+            aligner.merge_right(&mut rest).expect("Unable to merge the aligner back.");
+            self.free = aligner;
+        }
+
+        None
+    }
+
+    /// Move to a chunk with room for `size` bytes aligned to `align`.
+    ///
+    /// The next chunk in the chain is reused if it is large enough. Otherwise, a new chunk is
+    /// acquired and inserted after the current chunk.
+    fn next_chunk(&mut self, size: usize, align: usize) {
+        // Enough room for the allocation, regardless of where the body starts.
+        let needed = size + cmp::max(align, 1) - 1;
+
+        unsafe {
+            let next = if self.current.is_null() { self.first } else { (*self.current).next };
+
+            let chunk = if !next.is_null() && (*next).size - mem::size_of::<Chunk>() >= needed {
+                next
+            } else {
+                let chunk_size = cmp::max(self.chunk_size, mem::size_of::<Chunk>() + needed);
+                let chunk = allocator::alloc(chunk_size, mem::align_of::<Chunk>()) as *mut Chunk;
+                ptr::write(chunk, Chunk {
+                    next: next,
+                    size: chunk_size,
+                });
+
+                // Link it into the chain.
+                if self.current.is_null() {
+                    self.first = chunk;
+                } else {
+                    (*self.current).next = chunk;
+                }
+
+                chunk
+            };
+
+            self.current = chunk;
+            self.free = Arena::body(chunk);
+        }
+    }
+
+    /// Get the memory of a chunk, excluding the header.
+    ///
+    /// If `chunk` is null, an empty block is returned.
+    #[allow(cast_possible_wrap)]
+    unsafe fn body(chunk: *mut Chunk) -> Block {
+        if chunk.is_null() {
+            Block::empty(Pointer::empty())
+        } else {
+            Block::from_raw_parts(Pointer::new(chunk as *mut u8)
+                                      .offset(mem::size_of::<Chunk>() as isize),
+                                  (*chunk).size - mem::size_of::<Chunk>())
+        }
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        let mut chunk = self.first;
+
+        while !chunk.is_null() {
+            unsafe {
+                let next = (*chunk).next;
+                allocator::free(chunk as *mut u8, (*chunk).size);
+                chunk = next;
+
+                #[cfg(test)]
+                RELEASED_CHUNKS += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reset() {
+        let mut arena = Arena::new(256);
+
+        let a = arena.alloc(16, 1);
+        let b = arena.alloc(32, 8);
+        assert_eq!(b as usize % 8, 0);
+        assert!(b as usize >= a as usize + 16);
+
+        // This does not fit in the first chunk.
+        let c = arena.alloc(300, 1);
+
+        unsafe {
+            *a = 1;
+            *b = 2;
+            *c.offset(299) = 3;
+        }
+
+        arena.reset();
+
+        // The chunks are reused from the start.
+        assert_eq!(arena.alloc(16, 1), a);
+        assert_eq!(arena.alloc(300, 1), c);
+        assert!(arena.contains(b));
+        assert!(!arena.contains(&arena as *const _ as *const u8));
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut arena = Arena::new(64);
+        arena.reset();
+
+        assert!(!arena.alloc(0, 1).is_null());
+    }
+}
@@ -8,8 +8,87 @@
 use prelude::*;
 
 use {sys, fail};
+#[cfg(feature = "stats")]
+use stats;
+#[cfg(all(test, feature = "sanitizer"))]
+use sanitizer;
 
-use core::{ptr, cmp, mem, fmt};
+use core::{ptr, cmp, mem, fmt, ops, marker};
+
+/// The number of size classes.
+///
+/// The last class is the large class, holding every size above the other classes.
+pub const NUM_CLASSES: usize = 16;
+/// The binary logarithm of the size of the smallest size class.
+const MIN_CLASS_LOG: usize = 4;
+
+/// Calculate the number of bytes needed to round `x` up to a multiple of `align`.
+///
+/// `align` must be non-zero. Power-of-two alignments (the common case) are handled with a
+/// bitmask, and only other alignments fall back to division.
+#[inline]
+fn aligner(x: usize, align: usize) -> usize {
+    if align.is_power_of_two() {
+        align.wrapping_sub(x & (align - 1)) & (align - 1)
+    } else {
+        // To avoid wasting space on the case where `x` is already aligned, we calculate it modulo
+        // `align`.
+        (align - x % align) % align
+    }
+}
+
+/// Check that ralloc may write to (or read from) a region.
+///
+/// This stands in for the instrumentation of AddressSanitizer in the tests of the `sanitizer`
+/// feature, catching accesses of ralloc itself to poisoned memory. Otherwise, this does nothing.
+#[inline]
+#[allow(unused_variables)]
+fn check_access(ptr: *const u8, size: usize) {
+    #[cfg(all(test, feature = "sanitizer"))]
+    sanitizer::assert_unpoisoned(ptr, size);
+}
+
+/// Round `x` up to the nearest multiple of `align`.
+///
+/// `align` need not be a power of two. An alignment of 0 is treated as 1.
+#[inline]
+pub fn align_up(x: usize, align: usize) -> usize {
+    x + aligner(x, cmp::max(align, 1))
+}
+
+/// Round `x` up to the nearest multiple of `align`, checking for overflow.
+///
+/// This is like `align_up`, but returns `None` if the result does not fit in `usize`.
+#[inline]
+pub fn checked_align_up(x: usize, align: usize) -> Option<usize> {
+    x.checked_add(aligner(x, cmp::max(align, 1)))
+}
+
+/// Align a pointer upwards to `align`.
+///
+/// This returns the aligned pointer and the number of bytes skipped. It is the pointer-only
+/// counterpart of [`Block::align`](struct.Block.html#method.align), for when there is no block
+/// yet. Like it, `align` need not be a power of two, and an alignment of 0 is treated as 1.
+#[inline]
+pub fn align_pointer(ptr: *mut u8, align: usize) -> (*mut u8, usize) {
+    // Calculate the aligner, which defines the smallest size required as precursor to align the
+    // pointer to `align`.
+    let aligner = aligner(ptr as usize, cmp::max(align, 1));
+
+    ((ptr as usize + aligner) as *mut u8, aligner)
+}
+
+/// Get the number of bytes lost to alignment per object, when carving objects of `object_size`
+/// bytes aligned to `align`.
+///
+/// Objects placed back to back are `object_size + alignment_overhead(object_size, align)` bytes
+/// apart, so `count` objects fit in `count * (object_size + overhead)` bytes starting at an
+/// address aligned to `align`. If the start is not aligned, up to `align - 1` additional bytes
+/// are needed.
+#[inline]
+pub fn alignment_overhead(object_size: usize, align: usize) -> usize {
+    align_up(object_size, align) - object_size
+}
 
 /// A contiguous memory block.
 ///
@@ -37,8 +116,14 @@ pub struct Block {
 
 impl Block {
     /// Construct a block from its raw parts (pointer and size).
+    ///
+    /// In debug mode, this checks that the block does not wrap around the end of the address
+    /// space. Note that no alignment is required, since blocks can be split at any position.
     #[inline]
     pub unsafe fn from_raw_parts(ptr: Pointer<u8>, size: usize) ->  Block {
+        debug_assert!((*ptr as usize).checked_add(size).is_some(), "The block 0x{:x}[{}] wraps \
+                      around the address space.", *ptr as usize, size);
+
         Block {
             size: size,
             ptr: ptr,
@@ -46,15 +131,26 @@ impl Block {
     }
 
     /// BRK allocate a block.
+    ///
+    /// This calls the OOM handler if the program break cannot be extended.
     #[inline]
-    #[allow(cast_possible_wrap)]
     pub fn brk(size: usize) -> Block {
-        Block {
+        Block::try_brk(size).unwrap_or_else(|()| fail::oom())
+    }
+
+    /// BRK allocate a block, if possible.
+    ///
+    /// In contrast to [`brk`](#method.brk), this returns `Err(())` if the program break cannot be
+    /// extended, leaving the handling to the caller.
+    #[inline]
+    #[allow(cast_possible_wrap)]
+    pub fn try_brk(size: usize) -> Result<Block, ()> {
+        Ok(Block {
             size: size,
             ptr: unsafe {
-                Pointer::new(sys::sbrk(size as isize).unwrap_or_else(|()| fail::oom()))
+                Pointer::new(sys::sbrk(size as isize)?)
             },
-        }
+        })
     }
 
     /// Create an empty block starting at `ptr`.
@@ -96,10 +192,15 @@ impl Block {
     /// zero. The return value is `Ok(())` on success, and `Err(())` on failure (e.g., the blocks
     /// are not adjacent).
     ///
-    /// If you merge with a zero sized block, it will succeed, even if they are not adjacent.
+    /// Merging with a zero sized block always succeeds and leaves this block unchanged. The empty
+    /// block is expected to be adjacent nonetheless (i.e. placed at the end of this block), and
+    /// this is checked in debug builds, since merging an unrelated empty block usually means that
+    /// the caller confused the blocks.
     #[inline]
     pub fn merge_right(&mut self, block: &mut Block) -> Result<(), ()> {
         if block.is_empty() {
+            debug_assert!(self.left_to(block), "Merging with a non-adjacent empty block.");
+
             Ok(())
         } else if self.left_to(block) {
             // Since the end of `block` is bounded by the address space, adding them cannot
@@ -107,6 +208,9 @@ impl Block {
             self.size += block.pop().size;
             // We pop it to make sure it isn't aliased.
 
+            #[cfg(feature = "stats")]
+            stats::record_merge();
+
             Ok(())
         } else { Err(()) }
     }
@@ -122,10 +226,131 @@ impl Block {
         self.size
     }
 
+    /// Get the size class of some size.
+    ///
+    /// Size classes are powers of two: Class `n` holds the sizes in `(2^(n + 3), 2^(n + 4)]`, with
+    /// the exception of the first class (holding everything up to 16 bytes) and the large class
+    /// (holding everything bigger than the class before it).
+    #[inline]
+    pub fn size_class(size: usize) -> usize {
+        if size <= 1 << MIN_CLASS_LOG {
+            0
+        } else {
+            // Calculate the binary logarithm, rounded up.
+            let log = mem::size_of::<usize>() * 8 - (size - 1).leading_zeros() as usize;
+
+            cmp::min(log - MIN_CLASS_LOG, NUM_CLASSES - 1)
+        }
+    }
+
+    /// Get the (upper bound) size of some size class.
+    ///
+    /// This is the reverse of [`size_class`](#method.size_class), i.e. `size_class(class_size(n))
+    /// == n`. For the large class, this is its lower bound instead.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `class` is not a size class.
+    #[inline]
+    pub fn class_size(class: usize) -> usize {
+        assert!(class < NUM_CLASSES, "Invalid size class {}.", class);
+
+        1 << (class + MIN_CLASS_LOG)
+    }
+
     /// Is this block aligned to `align`?
+    ///
+    /// An alignment of 0 is treated as 1, i.e. every block is aligned to it.
     #[inline]
     pub fn aligned_to(&self, align: usize) -> bool {
-        *self.ptr as usize % align == 0
+        *self.ptr as usize % cmp::max(align, 1) == 0
+    }
+
+    /// Read the byte at index `i`.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `i` is out of bound.
+    #[inline]
+    #[allow(cast_possible_wrap)]
+    pub fn get(&self, i: usize) -> u8 {
+        // Bound check.
+        assert!(i < self.size, "Index {} out of bound (size is {}).", i, self.size);
+
+        // The index is bounded by the size, hence the conversion cannot overflow.
+        unsafe { *(*self.ptr).offset(i as isize) }
+    }
+
+    /// Write `v` to the byte at index `i`.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `i` is out of bound.
+    #[inline]
+    #[allow(cast_possible_wrap)]
+    pub fn set(&mut self, i: usize, v: u8) {
+        // Bound check.
+        assert!(i < self.size, "Index {} out of bound (size is {}).", i, self.size);
+
+        // The index is bounded by the size, hence the conversion cannot overflow.
+        unsafe { *(*self.ptr).offset(i as isize) = v; }
+    }
+
+    /// Volatile read the byte at index `offset`.
+    ///
+    /// This is like [`get`](#method.get), except that the read won't be elided or reordered by
+    /// the compiler, which is needed when the block is backed by device memory.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `offset` is out of bound.
+    #[inline]
+    #[allow(cast_possible_wrap)]
+    pub fn read_volatile_at(&self, offset: usize) -> u8 {
+        // Bound check.
+        assert!(offset < self.size, "Index {} out of bound (size is {}).", offset, self.size);
+
+        // The index is bounded by the size, hence the conversion cannot overflow.
+        unsafe { ptr::read_volatile((*self.ptr).offset(offset as isize)) }
+    }
+
+    /// Volatile write `byte` to the byte at index `offset`.
+    ///
+    /// This is like [`set`](#method.set), except that the write won't be elided or reordered by
+    /// the compiler, which is needed when the block is backed by device memory.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `offset` is out of bound.
+    #[inline]
+    #[allow(cast_possible_wrap)]
+    pub fn write_volatile_at(&mut self, offset: usize, byte: u8) {
+        // Bound check.
+        assert!(offset < self.size, "Index {} out of bound (size is {}).", offset, self.size);
+
+        // The index is bounded by the size, hence the conversion cannot overflow.
+        unsafe { ptr::write_volatile((*self.ptr).offset(offset as isize), byte); }
+    }
+
+    /// Hash the contents of the block.
+    ///
+    /// This uses 64-bit FNV-1a, a fast non-cryptographic hash, which can be used for detecting
+    /// corruption of critical buffers. Empty blocks hash to the FNV offset basis.
+    #[allow(cast_possible_wrap)]
+    pub fn content_hash(&self) -> u64 {
+        /// The FNV offset basis.
+        const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+        /// The FNV prime.
+        const PRIME: u64 = 0x100000001b3;
+
+        let mut hash = OFFSET_BASIS;
+        for i in 0..self.size {
+            // The index is bounded by the size, hence the conversion cannot overflow.
+            hash ^= unsafe { *(*self.ptr).offset(i as isize) } as u64;
+            hash = hash.wrapping_mul(PRIME);
+        }
+
+        hash
     }
 
     /// memcpy the block to another pointer.
@@ -135,11 +360,90 @@ impl Block {
     /// This will panic if the target block is smaller than the source.
     #[inline]
     pub fn copy_to(&self, block: &mut Block) {
+        self.try_copy_to(block).expect("Block too small.");
+    }
+
+    /// memcpy the block to another pointer, if it fits.
+    ///
+    /// The return value is `Ok(())` on success, and `Err(())` if the target block is smaller than
+    /// the source (in which case nothing is copied).
+    #[inline]
+    pub fn try_copy_to(&self, block: &mut Block) -> Result<(), ()> {
         // Bound check.
-        assert!(self.size <= block.size, "Block too small.");
+        if self.size <= block.size {
+            check_access(*self.ptr, self.size);
+            check_access(*block.ptr, self.size);
+
+            unsafe {
+                ptr::copy_nonoverlapping(*self.ptr, *block.ptr, self.size);
+            }
+
+            Ok(())
+        } else { Err(()) }
+    }
+
+    /// memcpy the first `len` bytes of the block to another block.
+    ///
+    /// This is useful when only a prefix of the data needs to be preserved (e.g. when the target
+    /// is smaller than the source).
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `len` exceeds the size of either block.
+    #[inline]
+    pub fn copy_prefix_to(&self, target: &mut Block, len: usize) {
+        assert!(len <= self.size, "Prefix {} out of bound (size is {})!", len, self.size);
+        assert!(len <= target.size, "Prefix {} does not fit the target (size is {})!", len,
+                target.size);
+
+        check_access(*self.ptr, len);
+        check_access(*target.ptr, len);
+
+        unsafe {
+            ptr::copy_nonoverlapping(*self.ptr, *target.ptr, len);
+        }
+    }
+
+    /// memcpy the contents of some blocks, one after another, into `target`.
+    ///
+    /// The sources are concatenated in order, starting at the start of `target`.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `target` is smaller than the total size of the sources, or if that
+    /// total overflows.
+    #[allow(cast_possible_wrap)]
+    pub fn gather_into(sources: &[&Block], target: &mut Block) {
+        let total = sources.iter()
+            .fold(Some(0), |acc, x| acc.and_then(|acc: usize| acc.checked_add(x.size)))
+            .expect("The total size of the sources overflows.");
+        assert!(total <= target.size, "The sources ({} bytes) do not fit the target (size is \
+                {})!", total, target.size);
+
+        check_access(*target.ptr, total);
+
+        let mut offset = 0;
+        for source in sources {
+            check_access(*source.ptr, source.size);
+
+            unsafe {
+                // The offset is bounded by the target's size, so this cannot overflow.
+                ptr::copy_nonoverlapping(*source.ptr, *target.ptr.clone().offset(offset as isize),
+                                         source.size);
+            }
+            offset += source.size;
+        }
+    }
+
+    /// Fill the block with `byte`.
+    ///
+    /// This is a `memset`, which is already vectorized for large sizes by the platform.
+    #[inline]
+    pub fn fill(&mut self, byte: u8) {
+        check_access(*self.ptr, self.size);
 
         unsafe {
-            ptr::copy_nonoverlapping(*self.ptr, *block.ptr, self.size);
+            ptr::write_bytes(*self.ptr, byte, self.size);
         }
     }
 
@@ -148,6 +452,8 @@ impl Block {
         use core::intrinsics;
 
         if cfg!(feature = "security") {
+            check_access(*self.ptr, self.size);
+
             unsafe {
                 intrinsics::volatile_set_memory(*self.ptr, 0, self.size);
             }
@@ -162,6 +468,39 @@ impl Block {
         unborrow!(mem::replace(self, Block::empty(self.ptr.clone())))
     }
 
+    /// "Pop" this block and return its pointer.
+    ///
+    /// Like [`pop`](#method.pop), this marks the block as free, but it returns the pointer to the
+    /// start of the old block instead of the block itself.
+    #[inline]
+    pub fn take_ptr(&mut self) -> Pointer<u8> {
+        self.size = 0;
+
+        // This won't alias, since the block is now empty.
+        unsafe { Pointer::new(*self.ptr) }
+    }
+
+    /// Compare the sizes of two blocks.
+    ///
+    /// Note that the `Ord` implementation of blocks is address-based (which the pool relies on),
+    /// this is for size-ordered views on top of it.
+    #[inline]
+    pub fn cmp_by_size(&self, other: &Block) -> cmp::Ordering {
+        self.size.cmp(&other.size)
+    }
+
+    /// Compare two blocks by size, breaking ties by address.
+    ///
+    /// In contrast to [`cmp_by_size`](#method.cmp_by_size), this is a total order, making sorting
+    /// deterministic. It can be used as comparator, e.g. `blocks.sort_by(Block::size_then_addr)`.
+    #[inline]
+    pub fn size_then_addr(a: &Block, b: &Block) -> cmp::Ordering {
+        match a.cmp_by_size(b) {
+            cmp::Ordering::Equal => a.cmp(b),
+            ord => ord,
+        }
+    }
+
     /// Is this block placed left to the given other block?
     #[inline]
     pub fn left_to(&self, to: &Block) -> bool {
@@ -179,6 +518,9 @@ impl Block {
     pub fn split(self, pos: usize) -> (Block, Block) {
         assert!(pos <= self.size, "Split {} out of bound (size is {})!", pos, self.size);
 
+        #[cfg(feature = "stats")]
+        stats::record_split();
+
         (
             Block {
                 size: pos,
@@ -197,22 +539,40 @@ impl Block {
 
     /// Split this block, such that the second block is aligned to `align`.
     ///
-    /// Returns an `None` holding the intact block if `align` is out of bounds.
+    /// Returns an `None` holding the intact block if `align` is out of bounds, or the aligned
+    /// pointer cannot be reached without wrapping.
+    ///
+    /// `align` need not be a power of two, the second block is simply placed at the first
+    /// multiple of `align` in the block. An alignment of 0 is treated as 1.
     #[inline]
-    #[allow(cast_possible_wrap)]
     pub fn align(&mut self, align: usize) -> Option<(Block, Block)> {
+        // Fast path: If the block is already aligned, there is nothing to split off.
+        if !self.is_empty() && self.aligned_to(align) {
+            // Invalidate the old block.
+            let old = self.pop();
+
+            return Some((old.empty_left(), old));
+        }
+
         // Calculate the aligner, which defines the smallest size required as precursor to align
         // the block to `align`.
-        let aligner = (align - *self.ptr as usize % align) % align;
-        //                                                 ^^^^^^^^
-        // To avoid wasting space on the case where the block is already aligned, we calculate it
-        // modulo `align`.
+        let (_, aligner) = align_pointer(*self.ptr, align);
 
         // Bound check.
         if aligner < self.size {
+            // Offset the pointer, refusing to wrap (e.g. for huge blocks, whose aligner does not
+            // fit in `isize`).
+            let ptr = match unsafe { self.ptr.clone().offset_checked(aligner) } {
+                Some(ptr) => ptr,
+                None => return None,
+            };
+
             // Invalidate the old block.
             let old = self.pop();
 
+            #[cfg(feature = "stats")]
+            stats::record_split();
+
             Some((
                 Block {
                     size: aligner,
@@ -220,15 +580,150 @@ impl Block {
                 },
                 Block {
                     size: old.size - aligner,
-                    ptr: unsafe {
-                        // The aligner is bounded by the size, which itself is bounded by the
-                        // address space. Therefore, this conversion cannot overflow.
-                        old.ptr.offset(aligner as isize)
-                    },
+                    ptr: ptr,
                 }
             ))
         } else { None }
     }
+
+    /// Iterate over the block in chunks of `chunk_size` bytes.
+    ///
+    /// The chunks are borrowed views into this block. The last chunk might be smaller than
+    /// `chunk_size`.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `chunk_size` is zero.
+    #[inline]
+    pub fn chunks(&self, chunk_size: usize) -> BlockChunks {
+        assert!(chunk_size != 0, "Chunk size must be non-zero.");
+
+        BlockChunks {
+            ptr: self.ptr.clone(),
+            size: self.size,
+            chunk_size: chunk_size,
+            _phantom: marker::PhantomData,
+        }
+    }
+
+    /// Split this block, such that the middle block both starts and ends on `align` boundaries.
+    ///
+    /// The returned blocks are the front padding, the aligned body, and the tail padding. The
+    /// body's size is a multiple of `align`. An alignment of 0 is treated as 1.
+    ///
+    /// Returns an `None` holding the intact block if no non-empty body fits in the block.
+    #[inline]
+    pub fn align_both(&mut self, align: usize) -> Option<(Block, Block, Block)> {
+        let align = cmp::max(align, 1);
+
+        // Calculate the aligner (see `align`).
+        let aligner = aligner(*self.ptr as usize, align);
+
+        // Bound check.
+        if aligner < self.size && self.size - aligner >= align {
+            // Round the remaining space down to a multiple of the alignment.
+            let body_size = (self.size - aligner) / align * align;
+
+            // Invalidate the old block.
+            let (padding, rest) = self.pop().split(aligner);
+            let (body, tail) = rest.split(body_size);
+
+            Some((padding, body, tail))
+        } else { None }
+    }
+
+    /// Carve a body of `size` bytes for SIMD access out of this block.
+    ///
+    /// The body is aligned to `lane_bytes`, and its size is `size` rounded up to a multiple of
+    /// `lane_bytes`. The rounded up bytes belong to the body, so they are safe to touch, e.g. when
+    /// a vector loop reads or writes a whole last lane.
+    ///
+    /// The returned blocks are the front padding, the body, and the tail.
+    ///
+    /// Returns an `None` holding the intact block if the body does not fit in the block (or its
+    /// size overflows).
+    #[inline]
+    pub fn carve_simd(&mut self, size: usize, lane_bytes: usize) -> Option<(Block, Block, Block)> {
+        let body_size = match checked_align_up(size, lane_bytes) {
+            Some(x) => x,
+            None => return None,
+        };
+        let (_, aligner) = align_pointer(*self.ptr, lane_bytes);
+
+        // Bound check.
+        if aligner <= self.size && self.size - aligner >= body_size {
+            // Invalidate the old block.
+            let (padding, rest) = self.pop().split(aligner);
+            let (body, tail) = rest.split(body_size);
+
+            Some((padding, body, tail))
+        } else { None }
+    }
+}
+
+/// A borrowed view of (a part of) a block.
+///
+/// This only gives immutable access to the block, hence it cannot be used to mutate or free the
+/// memory, preserving the guarantees of the block it is borrowed from.
+pub struct BlockRef<'a> {
+    /// The viewed block.
+    block: Block,
+    /// Associated phantom data.
+    ///
+    /// This indicates that we borrow the parent block.
+    _phantom: marker::PhantomData<&'a Block>,
+}
+
+impl<'a> ops::Deref for BlockRef<'a> {
+    type Target = Block;
+
+    #[inline]
+    fn deref(&self) -> &Block {
+        &self.block
+    }
+}
+
+/// An iterator over fixed-size chunks of a block.
+///
+/// See [`Block::chunks`](./struct.Block.html#method.chunks).
+pub struct BlockChunks<'a> {
+    /// The start of the remaining part of the block.
+    ptr: Pointer<u8>,
+    /// The size of the remaining part of the block.
+    size: usize,
+    /// The size of the chunks.
+    chunk_size: usize,
+    /// Associated phantom data.
+    ///
+    /// This indicates that we borrow the block.
+    _phantom: marker::PhantomData<&'a Block>,
+}
+
+impl<'a> Iterator for BlockChunks<'a> {
+    type Item = BlockRef<'a>;
+
+    #[inline]
+    #[allow(cast_possible_wrap)]
+    fn next(&mut self) -> Option<BlockRef<'a>> {
+        if self.size == 0 {
+            None
+        } else {
+            let size = cmp::min(self.chunk_size, self.size);
+            let ptr = self.ptr.clone();
+
+            // The chunk is bounded by the block, so this conversion cannot overflow.
+            self.ptr = unsafe { self.ptr.clone().offset(size as isize) };
+            self.size -= size;
+
+            Some(BlockRef {
+                block: Block {
+                    size: size,
+                    ptr: ptr,
+                },
+                _phantom: marker::PhantomData,
+            })
+        }
+    }
 }
 
 impl From<Block> for Pointer<u8> {
@@ -272,6 +767,12 @@ impl fmt::Debug for Block {
 mod test {
     use prelude::*;
 
+    use super::*;
+
+    use core::{cmp, isize};
+
+    use sys;
+
     #[test]
     fn test_array() {
         let arr = b"Lorem ipsum dolor sit amet";
@@ -304,7 +805,22 @@ mod test {
 
         let mut tmp = rest.split(0).0;
         assert!(tmp.is_empty());
-        lorem.split(2).0.merge_right(&mut tmp).unwrap();
+        lorem.split(5).0.merge_right(&mut tmp).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn test_merge_unrelated_empty() {
+        let arr = b"Lorem ipsum dolor sit amet";
+        let block = unsafe {
+            Block::from_raw_parts(Pointer::new(arr.as_ptr() as *mut u8), arr.len())
+        };
+
+        let (mut lorem, rest) = block.split(5);
+        // An empty block at the end of `rest`, far away from the end of `lorem`.
+        let mut tmp = rest.empty_right();
+        let _ = lorem.merge_right(&mut tmp);
     }
 
     #[test]
@@ -333,6 +849,461 @@ mod test {
         assert_eq!(arr, [0, 2, 0, 2, 255, 255]);
     }
 
+    #[test]
+    fn test_try_copy() {
+        let mut arr = [1u8, 2, 3, 0, 0, 0, 0];
+
+        let block = unsafe {
+            Block::from_raw_parts(Pointer::new(&mut arr[0] as *mut u8), 7)
+        };
+
+        let (a, b) = block.split(3);
+        let (mut b, mut c) = b.split(3);
+        a.try_copy_to(&mut b).unwrap();
+        a.try_copy_to(&mut c).unwrap_err();
+
+        assert_eq!(arr, [1, 2, 3, 1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn test_copy_prefix() {
+        let mut arr = [1u8, 2, 3, 4, 5, 6, 0, 0, 0];
+
+        let block = unsafe {
+            Block::from_raw_parts(Pointer::new(&mut arr[0] as *mut u8), 9)
+        };
+
+        let (a, mut b) = block.split(6);
+        a.copy_prefix_to(&mut b, 3);
+
+        assert_eq!(arr, [1, 2, 3, 4, 5, 6, 1, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_copy_prefix_oob() {
+        let mut arr = [0u8; 9];
+
+        let block = unsafe {
+            Block::from_raw_parts(Pointer::new(&mut arr[0] as *mut u8), 9)
+        };
+
+        let (a, mut b) = block.split(6);
+        a.copy_prefix_to(&mut b, 4);
+    }
+
+    #[test]
+    fn test_gather_into() {
+        let mut arr = [1u8, 2, 3, 4, 5, 6, 0, 0, 0, 0, 0, 0, 0];
+
+        let block = unsafe {
+            Block::from_raw_parts(Pointer::new(&mut arr[0] as *mut u8), 13)
+        };
+
+        let (a, rest) = block.split(1);
+        let (b, rest) = rest.split(2);
+        let (c, mut target) = rest.split(3);
+        Block::gather_into(&[&c, &a, &b], &mut target);
+
+        assert_eq!(arr, [1, 2, 3, 4, 5, 6, 4, 5, 6, 1, 2, 3, 0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_gather_into_oob() {
+        let mut arr = [0u8; 9];
+
+        let block = unsafe {
+            Block::from_raw_parts(Pointer::new(&mut arr[0] as *mut u8), 9)
+        };
+
+        let (a, rest) = block.split(3);
+        let (b, mut target) = rest.split(3);
+        Block::gather_into(&[&a, &b], &mut target);
+    }
+
+    #[test]
+    fn test_get_set() {
+        let mut arr = [0u8, 1, 2, 3];
+
+        let mut block = unsafe {
+            Block::from_raw_parts(Pointer::new(&mut arr[0] as *mut u8), 4)
+        };
+
+        assert_eq!(block.get(2), 2);
+        block.set(2, 200);
+        block.set(3, 42);
+        assert_eq!(block.get(2), 200);
+        assert_eq!(block.get(3), 42);
+
+        assert_eq!(arr, [0, 1, 200, 42]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_get_oob() {
+        let arr = b"lorem";
+        let block = unsafe {
+            Block::from_raw_parts(Pointer::new(arr.as_ptr() as *mut u8), arr.len())
+        };
+
+        block.get(5);
+    }
+
+    #[test]
+    fn test_fill() {
+        let mut arr = [0u8; 8];
+
+        let mut block = unsafe {
+            Block::from_raw_parts(Pointer::new(&mut arr[2] as *mut u8), 4)
+        };
+        block.fill(0xCC);
+
+        assert_eq!(arr, [0, 0, 0xCC, 0xCC, 0xCC, 0xCC, 0, 0]);
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let mut arr1 = *b"Lorem ipsum";
+        let arr2 = *b"Lorem ipsum";
+
+        let mut block1 = unsafe {
+            Block::from_raw_parts(Pointer::new(&mut arr1[0] as *mut u8), arr1.len())
+        };
+        let block2 = unsafe {
+            Block::from_raw_parts(Pointer::new(arr2.as_ptr() as *mut u8), arr2.len())
+        };
+
+        assert_eq!(block1.content_hash(), block2.content_hash());
+        assert_eq!(block1.empty_left().content_hash(), 0xcbf29ce484222325);
+
+        block1.set(6, b'I');
+        assert!(block1.content_hash() != block2.content_hash());
+    }
+
+    #[test]
+    fn test_fill_large() {
+        let mut arr = [0u8; 4099];
+
+        // Use an unaligned start and size, to hit the head and tail of wide stores.
+        let mut block = unsafe {
+            Block::from_raw_parts(Pointer::new(&mut arr[1] as *mut u8), 4097)
+        };
+        block.fill(0x5A);
+
+        assert_eq!(arr[0], 0);
+        assert!(arr[1..4098].iter().all(|&x| x == 0x5A));
+        assert_eq!(arr[4098], 0);
+    }
+
+    #[test]
+    fn test_volatile() {
+        let mut arr = [0u8; 8];
+
+        let mut block = unsafe {
+            Block::from_raw_parts(Pointer::new(&mut arr[0] as *mut u8), 8)
+        };
+
+        block.write_volatile_at(5, 0xAB);
+        assert_eq!(block.read_volatile_at(5), 0xAB);
+        assert_eq!(block.read_volatile_at(4), 0);
+
+        assert_eq!(arr, [0, 0, 0, 0, 0, 0xAB, 0, 0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_volatile_oob() {
+        let mut arr = [0u8; 8];
+
+        let mut block = unsafe {
+            Block::from_raw_parts(Pointer::new(&mut arr[0] as *mut u8), 8)
+        };
+
+        block.write_volatile_at(8, 1);
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn test_wrapping_block() {
+        unsafe {
+            let _ = Block::from_raw_parts(Pointer::new((!0usize - 4) as *mut u8), 8);
+        }
+    }
+
+    #[test]
+    fn test_align_aligned() {
+        let mut arr = [0u64; 4];
+        let ptr = &mut arr[0] as *mut u64 as *mut u8;
+
+        let mut block = unsafe {
+            Block::from_raw_parts(Pointer::new(ptr), 32)
+        };
+
+        let (padding, body) = block.align(8).unwrap();
+        assert!(padding.is_empty());
+        assert!(block.is_empty());
+        assert_eq!(padding, body);
+        assert_eq!(body.size(), 32);
+        assert_eq!(*Pointer::from(body), ptr);
+    }
+
+    #[test]
+    fn test_carve_simd() {
+        let mut arr = [0u8; 64];
+        let mut block = unsafe {
+            Block::from_raw_parts(Pointer::new(&mut arr[0] as *mut u8), 64)
+        };
+
+        let (padding, body, tail) = block.carve_simd(13, 16).unwrap();
+        assert!(body.aligned_to(16));
+        assert_eq!(body.size(), 16);
+        assert!(padding.size() < 16);
+        assert_eq!(padding.size() + body.size() + tail.size(), 64);
+        assert!(padding.left_to(&body));
+        assert!(body.left_to(&tail));
+
+        // The body does not fit.
+        let mut small = unsafe {
+            Block::from_raw_parts(Pointer::new(&mut arr[0] as *mut u8), 16)
+        };
+        assert!(small.carve_simd(20, 16).is_none());
+        assert_eq!(small.size(), 16);
+
+        // Rounding the size up overflows.
+        assert!(small.carve_simd(!0 - 3, 16).is_none());
+        assert_eq!(small.size(), 16);
+    }
+
+    #[test]
+    fn test_align_no_wrap() {
+        // A fake block, which is never dereferenced, whose aligner does not fit in `isize`.
+        let mut block = unsafe {
+            Block::from_raw_parts(Pointer::new(0x10 as *mut u8), isize::MAX as usize + 0x100)
+        };
+        let align = isize::MAX as usize + 0x20;
+
+        assert!(block.align(align).is_none());
+        // The block is left intact.
+        assert_eq!(block.size(), isize::MAX as usize + 0x100);
+    }
+
+    #[test]
+    fn test_align_zero() {
+        let mut arr = [0u8; 16];
+        let mut block = unsafe {
+            Block::from_raw_parts(Pointer::new(&mut arr[1] as *mut u8), 8)
+        };
+
+        assert!(block.aligned_to(0));
+
+        let (a, b) = block.align(0).unwrap();
+        assert!(a.is_empty());
+        assert_eq!(b.size(), 8);
+    }
+
+    #[test]
+    fn test_align_non_power_of_two() {
+        let mut arr = [0u8; 64];
+        let mut block = unsafe {
+            Block::from_raw_parts(Pointer::new(&mut arr[0] as *mut u8), 64)
+        };
+
+        let (a, b) = block.align(24).unwrap();
+        assert!(b.aligned_to(24));
+        assert!(a.size() < 24);
+        assert_eq!(a.size() + b.size(), 64);
+        assert!(a.left_to(&b));
+    }
+
+    #[test]
+    fn test_take_ptr() {
+        let arr = b"Lorem ipsum dolor sit amet";
+        let mut block = unsafe {
+            Block::from_raw_parts(Pointer::new(arr.as_ptr() as *mut u8), arr.len())
+        };
+
+        let ptr = block.take_ptr();
+        assert!(block.is_empty());
+        assert_eq!(*ptr as *const u8, arr.as_ptr());
+        assert_eq!(*Pointer::from(block) as *const u8, arr.as_ptr());
+    }
+
+    #[test]
+    fn test_align_both() {
+        let mut arr = [0u64; 8];
+        let ptr = &mut arr[0] as *mut u64 as *mut u8;
+
+        let mut block = unsafe {
+            Block::from_raw_parts(Pointer::new(ptr).offset(3), 40)
+        };
+
+        let (padding, body, tail) = block.align_both(8).unwrap();
+        assert!(block.is_empty());
+        assert_eq!(padding.size(), 5);
+        assert_eq!(body.size(), 32);
+        assert_eq!(tail.size(), 3);
+        assert!(body.aligned_to(8));
+        assert!(padding.left_to(&body));
+        assert!(body.left_to(&tail));
+
+        let mut block = unsafe {
+            Block::from_raw_parts(Pointer::new(ptr).offset(3), 10)
+        };
+
+        assert!(block.align_both(8).is_none());
+        assert_eq!(block.size(), 10);
+
+        // An alignment of 0 is treated as 1.
+        let (padding, body, tail) = block.align_both(0).unwrap();
+        assert!(padding.is_empty());
+        assert_eq!(body.size(), 10);
+        assert!(tail.is_empty());
+    }
+
+    #[test]
+    fn test_chunks() {
+        let arr = b"Lorem ipsum";
+        let block = unsafe {
+            Block::from_raw_parts(Pointer::new(arr.as_ptr() as *mut u8), arr.len())
+        };
+
+        let mut chunks = block.chunks(4);
+        for (n, &size) in [4, 4, 3].iter().enumerate() {
+            let chunk = chunks.next().unwrap();
+
+            assert_eq!(chunk.size(), size);
+            assert_eq!(chunk.get(0), arr[4 * n]);
+        }
+        assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_chunks() {
+        let arr = b"Lorem ipsum";
+        let block = unsafe {
+            Block::from_raw_parts(Pointer::new(arr.as_ptr() as *mut u8), arr.len())
+        };
+
+        block.chunks(0);
+    }
+
+    #[test]
+    fn test_align_up() {
+        assert_eq!(align_up(0, 8), 0);
+        assert_eq!(align_up(1, 8), 8);
+        assert_eq!(align_up(8, 8), 8);
+        assert_eq!(align_up(9, 8), 16);
+        assert_eq!(align_up(7, 3), 9);
+        assert_eq!(align_up(7, 0), 7);
+
+        assert_eq!(checked_align_up(9, 8), Some(16));
+        assert_eq!(checked_align_up(!0, 1), Some(!0));
+        assert_eq!(checked_align_up(!0 - 3, 8), None);
+    }
+
+    #[test]
+    fn test_aligner_fast_path() {
+        for x in 0..1024 {
+            for shift in 0..12 {
+                let align = 1 << shift;
+                let addr = x * 7 + (!0 << 16) + 3;
+
+                assert_eq!(aligner(x, align), (align - x % align) % align);
+                assert_eq!(aligner(addr, align), (align - addr % align) % align);
+            }
+        }
+
+        // Non-power-of-two alignments take the slow path.
+        assert_eq!(aligner(7, 3), 2);
+        assert_eq!(aligner(9, 3), 0);
+    }
+
+    #[test]
+    fn test_align_pointer() {
+        let mut arr = [0u8; 64];
+
+        for offset in 0..8 {
+            for &align in &[0, 1, 2, 8, 13, 16] {
+                let ptr = &mut arr[offset] as *mut u8;
+                let mut block = unsafe {
+                    Block::from_raw_parts(Pointer::new(ptr), 32)
+                };
+
+                let (aligned, skipped) = align_pointer(ptr, align);
+                let (a, b) = block.align(align).unwrap();
+
+                assert_eq!(skipped, a.size());
+                assert_eq!(aligned, *Pointer::from(b));
+            }
+        }
+    }
+
+    #[test]
+    fn test_alignment_overhead() {
+        for &(size, align) in &[(1, 1), (3, 4), (8, 8), (10, 8), (24, 16), (5, 3), (1, 32)] {
+            let mut arr = [0u8; 1024];
+            let mut block = unsafe {
+                Block::from_raw_parts(Pointer::new(&mut arr[0] as *mut u8), 1024)
+            };
+
+            // Start at an aligned address.
+            let (_, mut rest) = block.align(align).unwrap();
+            let start = *Pointer::from(rest.empty_left()) as usize;
+
+            // Carve 8 objects.
+            for _ in 0..8 {
+                let (_, aligned) = rest.align(align).unwrap();
+                let (_, next) = aligned.split(size);
+                rest = next;
+            }
+            let (_, rest) = rest.align(align).unwrap();
+            let end = *Pointer::from(rest.empty_left()) as usize;
+
+            assert_eq!(end - start, 8 * (size + alignment_overhead(size, align)));
+        }
+    }
+
+    #[test]
+    fn test_size_class() {
+        assert_eq!(Block::size_class(0), 0);
+        assert_eq!(Block::size_class(1), 0);
+        assert_eq!(Block::size_class(16), 0);
+        assert_eq!(Block::size_class(17), 1);
+        assert_eq!(Block::size_class(31), 1);
+        assert_eq!(Block::size_class(32), 1);
+        assert_eq!(Block::size_class(33), 2);
+        assert_eq!(Block::size_class(1 << 20), NUM_CLASSES - 1);
+        assert_eq!(Block::size_class(!0), NUM_CLASSES - 1);
+
+        for class in 0..NUM_CLASSES {
+            assert_eq!(Block::size_class(Block::class_size(class)), class);
+        }
+    }
+
+    #[test]
+    fn test_cmp_by_size() {
+        let mut arr = [0u8; 32];
+        let (a, rest) = unsafe {
+            Block::from_raw_parts(Pointer::new(&mut arr[0] as *mut u8), 32)
+        }.split(8);
+        let (b, c) = rest.split(16);
+
+        // `a` is left to `b`, but smaller.
+        assert_eq!(a.cmp_by_size(&b), cmp::Ordering::Less);
+        assert_eq!(b.cmp_by_size(&c), cmp::Ordering::Greater);
+        assert_eq!(a.cmp_by_size(&c), cmp::Ordering::Equal);
+        assert_eq!(a.cmp(&b), cmp::Ordering::Less);
+
+        // Ties are broken by address.
+        assert_eq!(Block::size_then_addr(&a, &c), cmp::Ordering::Less);
+        assert_eq!(Block::size_then_addr(&c, &a), cmp::Ordering::Greater);
+        assert_eq!(Block::size_then_addr(&b, &c), cmp::Ordering::Greater);
+        assert_eq!(Block::size_then_addr(&a, &a), cmp::Ordering::Equal);
+    }
+
     #[test]
     fn test_empty_lr() {
         let arr = b"Lorem ipsum dolor sit amet";
@@ -353,4 +1324,19 @@ mod test {
 
         assert!(brk1 < brk2);
     }
+
+    #[test]
+    fn test_try_brk_fail() {
+        unsafe {
+            sys::SBRK_LIMIT = 64;
+        }
+
+        let res = Block::try_brk(65);
+
+        unsafe {
+            sys::SBRK_LIMIT = !0;
+        }
+
+        assert!(res.is_err());
+    }
 }
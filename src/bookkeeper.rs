@@ -3,7 +3,10 @@
 use prelude::*;
 
 use core::ops::Range;
-use core::{ptr, mem, ops};
+use core::{ptr, mem, ops, cmp};
+
+#[cfg(feature = "sanitizer")]
+use sanitizer;
 
 /// Elements required _more_ than the length as capacity.
 ///
@@ -18,14 +21,147 @@ use core::{ptr, mem, ops};
 /// See assumption 4.
 pub const EXTRA_ELEMENTS: usize = 4;
 
-#[cfg(feature = "alloc_id")]
-use core::sync::atomic::{self, AtomicUsize};
+use core::sync::atomic::{self, AtomicUsize, AtomicBool};
 /// The bookkeeper ID count.
 ///
 /// This is atomically incremented whenever a new `Bookkeeper` is created.
 #[cfg(feature = "alloc_id")]
 static BOOKKEEPER_ID_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
+/// The value of `FREE_SCRIBBLE` representing that no scribbling is done.
+const NO_SCRIBBLE: usize = !0;
+/// The byte freed blocks are filled with, or `NO_SCRIBBLE`.
+static FREE_SCRIBBLE: AtomicUsize = AtomicUsize::new(NO_SCRIBBLE);
+
+/// Set the byte pattern freed blocks are filled with.
+///
+/// With `Some(byte)`, every block freed (or moved away from by reallocation) is filled with `byte`,
+/// making use-after-free easier to spot in a debugger. `None` disables this, which is the default.
+///
+/// In contrast to the `security` feature, this can be changed at runtime.
+#[inline]
+pub fn set_free_scribble(pattern: Option<u8>) {
+    FREE_SCRIBBLE.store(pattern.map_or(NO_SCRIBBLE, |x| x as usize), atomic::Ordering::Relaxed);
+}
+
+/// Fill a block about to be freed with the scribble pattern, if any.
+#[inline]
+#[allow(cast_possible_truncation)]
+fn scribble(block: &mut Block) {
+    let pattern = FREE_SCRIBBLE.load(atomic::Ordering::Relaxed);
+
+    if pattern != NO_SCRIBBLE {
+        block.fill(pattern as u8);
+    }
+}
+
+/// The byte free blocks are filled with, when compiled with `paranoid`.
+#[cfg(feature = "paranoid")]
+const CANARY: u8 = 0x5A;
+/// The number of bytes of the canary checked at either end of a free block.
+#[cfg(feature = "paranoid")]
+const CANARY_CHECK_LEN: usize = 64;
+
+/// Fill a block entering the pool with the canary.
+///
+/// When compiled with `paranoid`, every free block is filled with the canary, which is checked
+/// (see [`check_canaries`](struct.Bookkeeper.html#method.check_canaries)) to catch writes to free
+/// memory early. This overrides the scribble pattern. Without `paranoid`, this does nothing.
+#[inline]
+#[allow(unused_variables)]
+fn fill_canary(block: &mut Block) {
+    #[cfg(feature = "paranoid")]
+    block.fill(CANARY);
+}
+
+/// Mark a block entering the pool as unaddressable.
+///
+/// When compiled with `sanitizer`, every block in the pool is kept poisoned, so accesses to free
+/// memory are caught. Without `sanitizer`, this does nothing.
+#[inline]
+#[allow(unused_variables)]
+fn poison(block: &Block) {
+    #[cfg(feature = "sanitizer")]
+    sanitizer::freed(*Pointer::from(block.empty_left()), block.size());
+}
+
+/// Mark a block leaving the pool (or about to be written by ralloc) as addressable.
+///
+/// This is the counterpart of [`poison`](fn.poison.html). Without `sanitizer`, this does nothing.
+#[inline]
+#[allow(unused_variables)]
+fn unpoison(block: &Block) {
+    #[cfg(feature = "sanitizer")]
+    sanitizer::allocated(*Pointer::from(block.empty_left()), block.size(), 0);
+}
+
+/// The number of times the pool was grown in the middle of an insertion, on this thread.
+///
+/// This is used to check that the pre-reservation makes these rare.
+#[cfg(test)]
+#[thread_local]
+static mut INLINE_GROWTHS: usize = 0;
+
+/// The factor, by which the alignment padding may exceed the size of an allocation, before a
+/// warning is logged.
+#[cfg(feature = "log")]
+const ALIGNMENT_WASTE_FACTOR: usize = 4;
+
+/// The number of alignment waste warnings logged on this thread.
+#[cfg(all(test, feature = "log"))]
+#[thread_local]
+static mut ALIGNMENT_WARNINGS: usize = 0;
+
+/// Zero blocks served from the pool?
+static ZERO_ON_ALLOC: AtomicBool = AtomicBool::new(false);
+
+/// Set whether every allocation is zeroed.
+///
+/// When enabled, blocks served from the free memory are zeroed before they are handed out, so an
+/// allocation never sees what a freed buffer held in the pool. This is disabled by default.
+///
+/// Only blocks taken from the pool are zeroed. Fresh memory from the OS is handed out as is, which
+/// is zero only if the OS gives it out zeroed. This does not hold for memory, which was given back
+/// (e.g. by `drain_to_os`) and obtained again, nor for the `sbrk` emulation of the shim. The bytes
+/// a buffer grows into when reallocated inplace are not zeroed either, so they might hold the
+/// contents of freed buffers.
+///
+/// This costs a write of every byte allocated from the free memory, which is roughly the cost of
+/// a `memset` per allocation. In contrast to the `security` feature (zeroing on free), it can be
+/// changed at runtime.
+#[inline]
+pub fn set_zero_on_alloc(enabled: bool) {
+    ZERO_ON_ALLOC.store(enabled, atomic::Ordering::Relaxed);
+}
+
+/// The number of bytes backing the pools of all the bookkeepers.
+static POOL_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Get the number of bytes backing the pools of all the bookkeepers.
+///
+/// This is the memory used by the allocators to keep track of the free blocks.
+#[inline]
+pub fn pool_bytes() -> usize {
+    POOL_BYTES.load(atomic::Ordering::Relaxed)
+}
+
+/// The maximal number of free blocks searched for a fitting block, per allocation.
+static SEARCH_BOUND: AtomicUsize = AtomicUsize::new(!0);
+
+/// Set the maximal number of free blocks searched for a fitting block, per allocation.
+///
+/// Allocation takes the first free block fitting it. With a bound, the search gives up after `n`
+/// blocks, and the allocation is served from fresh memory instead. This caps the latency of
+/// allocation when many small free blocks precede any fitting one, at the cost of growing the
+/// heap more often. The default is unbounded (`!0`).
+///
+/// The bound counts the entries of the pool, not only the free blocks: Empty entries, which are
+/// left behind when blocks are taken out of the middle of the pool, count toward it as well.
+#[inline]
+pub fn set_search_bound(n: usize) {
+    SEARCH_BOUND.store(n, atomic::Ordering::Relaxed);
+}
+
 /// The memory bookkeeper.
 ///
 /// This stores data about the state of the allocator, and in particular, the free memory.
@@ -51,6 +187,13 @@ pub struct Bookkeeper {
     ///
     /// These are **not** invariants: If these assumpptions are not held, it will simply act strange
     /// (e.g. logic bugs), but not memory unsafety.
+    ///
+    /// # Bootstrapping
+    ///
+    /// The pool itself lives in memory obtained from the allocator, so growing it can recurse into
+    /// the allocator. This is bounded by assumption 4 and the `reserving` flag: while reserving,
+    /// no further reservation happens, and the extra capacity is enough to hold the blocks pushed
+    /// by the reservation itself (see [`EXTRA_ELEMENTS`](./constant.EXTRA_ELEMENTS.html)).
     pool: Vec<Block>,
     /// Is this bookkeeper currently reserving?
     ///
@@ -58,6 +201,11 @@ pub struct Bookkeeper {
     ///
     // TODO: Find a replacement for this "hack".
     reserving: bool,
+    /// The total number of bytes in the pool.
+    ///
+    /// This is kept in sync with the sizes of the blocks in the pool, which is verified by the
+    /// consistency check.
+    total_bytes: usize,
     /// The allocator ID.
     ///
     /// This is simply to be able to distinguish allocators in the locks.
@@ -72,11 +220,14 @@ impl Bookkeeper {
         debug_assert!(vec.capacity() >= EXTRA_ELEMENTS, "Not enough initial capacity of the vector.");
         debug_assert!(vec.is_empty(), "Initial vector isn't empty.");
 
+        POOL_BYTES.fetch_add(vec.capacity() * mem::size_of::<Block>(), atomic::Ordering::Relaxed);
+
         // TODO: When added use expr field attributes.
         #[cfg(feature = "alloc_id")]
         let res = Bookkeeper {
             pool: vec,
             reserving: false,
+            total_bytes: 0,
             // Increment the ID counter to get a brand new ID.
             id: BOOKKEEPER_ID_COUNTER.fetch_add(1, atomic::Ordering::SeqCst),
         };
@@ -84,6 +235,7 @@ impl Bookkeeper {
         let res = Bookkeeper {
             pool: vec,
             reserving: false,
+            total_bytes: 0,
         };
 
         log!(res, "Bookkeeper created.");
@@ -149,6 +301,29 @@ impl Bookkeeper {
         left_ind..right_ind
     }
 
+    /// Get the blocks of the pool.
+    ///
+    /// Note that this includes empty blocks.
+    #[inline]
+    #[cfg(any(test, feature = "debug_tools"))]
+    pub fn blocks(&self) -> &[Block] {
+        &self.pool
+    }
+
+    /// Get the number of bytes in the pool.
+    #[inline]
+    pub fn total_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    /// Get the size of the largest block in the pool.
+    ///
+    /// This is 0 if the pool is empty.
+    #[inline]
+    pub fn largest_free(&self) -> usize {
+        self.pool.iter().map(Block::size).max().unwrap_or(0)
+    }
+
     /// Go over every block in the allocator and call some function.
     ///
     /// Technically, this could be done through an iterator, but this, more unidiomatic, way is
@@ -163,9 +338,68 @@ impl Bookkeeper {
         }
 
         // Take the block holding the pool.
+        POOL_BYTES.fetch_sub(self.pool.capacity() * mem::size_of::<Block>(),
+                             atomic::Ordering::Relaxed);
         f(Block::from(self.pool));
     }
 
+    /// Find the first block fitting an allocation, searching at most `bound` blocks.
+    ///
+    /// The fitting block is split at the aligner, which is left in its place in the pool, while
+    /// the aligned rest is returned along with its index. Note that the byte count is not updated.
+    /// If none of the first `bound` blocks fits, the pool is left untouched, and `None` is
+    /// returned.
+    fn first_fit(&mut self, size: usize, align: usize, bound: usize) -> Option<(usize, Block)> {
+        self.pool.iter_mut().enumerate().take(bound).filter_map(|(n, i)| {
+            if i.size() >= size {
+                // Try to split at the aligner.
+                i.align(align).and_then(|(mut a, mut b)| {
+                    if b.size() >= size {
+                        // Override the old block.
+                        *i = a;
+                        Some((n, b))
+                    } else {
+                        // Put the split block back together and place it back in its spot.
+                        a.merge_right(&mut b).expect("Unable to merge block right.");
+                        *i = a;
+                        None
+                    }
+                })
+            } else {
+                None
+            }
+        }).next()
+    }
+
+    /// Check the canaries of the free blocks around some index.
+    ///
+    /// When compiled with `paranoid`, the blocks at `ind - 1`, `ind`, and `ind + 1` are checked to
+    /// still be filled with the canary at either end. This catches corruption of free memory (e.g.
+    /// use-after-free) in the neighborhood of every operation, rather than only when the corrupted
+    /// block is handed out again. Without `paranoid`, this does nothing.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if a canary is overwritten.
+    #[inline]
+    #[allow(unused_variables)]
+    #[allow(cast_possible_wrap)]
+    fn check_canaries(&self, ind: usize) {
+        #[cfg(feature = "paranoid")]
+        for block in &self.pool[ind.saturating_sub(1)..cmp::min(ind + 2, self.pool.len())] {
+            let ptr = *Pointer::from(block.empty_left());
+            let size = block.size();
+            let len = cmp::min(size, CANARY_CHECK_LEN);
+
+            unpoison(block);
+            for i in (0..len).chain(size - len..size) {
+                assert!(unsafe { *ptr.offset(i as isize) } == CANARY, "The free block {:?} is \
+                        corrupted at offset {} (use after free?).", block, i);
+            }
+            poison(block);
+        }
+    }
+
     /// Perform consistency checks.
     ///
     /// This will check for the following conditions:
@@ -187,6 +421,11 @@ impl Bookkeeper {
                     "The capacity should be at least {} more than the length of the pool.",
                     EXTRA_ELEMENTS);
 
+            // Check that the byte count is kept in sync.
+            let total_bytes = self.pool.iter().fold(0, |acc, x| acc + x.size());
+            assert!(total_bytes == self.total_bytes, "The byte count ({}) does not match the \
+                    pool ({}).", self.total_bytes, total_bytes);
+
             if let Some((_, x)) = it.next() {
                 // Make sure there are no leading empty blocks.
                 assert!(!x.is_empty(), "The leading block is empty.");
@@ -243,6 +482,17 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
     /// prior to call of this function, it should be too after it.
     fn alloc_fresh(&mut self, size: usize, align: usize) -> Block;
 
+    /// Called after memory has been freed into the pool.
+    ///
+    /// This can be used to move memory elsewhere (e.g. back to the global allocator) when the pool
+    /// holds too much of it. The default implementation does nothing.
+    ///
+    /// # Assumptions
+    ///
+    /// This is called when the pool is in a consistent state, and should leave it as such.
+    #[inline]
+    fn on_new_memory(&mut self) {}
+
     /// Allocate a chunk of memory.
     ///
     /// This function takes a size and an alignment. From these a fitting block is found, to which
@@ -289,43 +539,38 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
         // Logging.
         log!(self, "Allocating {} bytes with alignment {}.", size, align);
 
-        if let Some((n, b)) = self.pool.iter_mut().enumerate().filter_map(|(n, i)| {
-            if i.size() >= size {
-                // Try to split at the aligner.
-                i.align(align).and_then(|(mut a, mut b)| {
-                    if b.size() >= size {
-                        // Override the old block.
-                        *i = a;
-                        Some((n, b))
-                    } else {
-                        // Put the split block back together and place it back in its spot.
-                        a.merge_right(&mut b).expect("Unable to merge block right.");
-                        *i = a;
-                        None
-                    }
-                })
-            } else {
-                None
+        // Warn about allocations, which might be mostly padding, as these are likely caused by a
+        // misconfigured caller.
+        #[cfg(feature = "log")]
+        {
+            if align.saturating_sub(1) > size.saturating_mul(ALIGNMENT_WASTE_FACTOR) {
+                log!(self, "Warning: Allocating {} bytes with alignment {} might waste up to {} \
+                     bytes on padding.", size, align, align - 1);
+
+                #[cfg(test)]
+                unsafe { ALIGNMENT_WARNINGS += 1; }
             }
-        }).next() {
+        }
+
+        let bound = SEARCH_BOUND.load(atomic::Ordering::Relaxed);
+        if let Some((n, b)) = self.first_fit(size, align, bound) {
+            self.check_canaries(n);
+            unpoison(&b);
+
+            // The block is no longer part of the pool.
+            self.total_bytes -= b.size();
+
             if self.pool[n].is_empty() {
                 // For empty alignment invariant.
                 let _ = self.remove_at(n);
             }
 
-            let (res, excessive) = b.split(size);
-
-            // Mark the excessive space as free.
-            // There are many corner cases that make knowing where to insert it difficult
-            // so we search instead.
-            let bound = self.find_bound(&excessive);
-            self.free_bound(bound, excessive);
+            // Carve the block. It is already aligned, so only the excessive space is freed.
+            let mut res = self.satisfy(b, size, align);
 
-            // Check consistency.
-            self.check();
-            debug_assert!(res.aligned_to(align), "Alignment failed.");
-            debug_assert!(res.size() == size, "Requested space does not match with the returned \
-                          block.");
+            if ZERO_ON_ALLOC.load(atomic::Ordering::Relaxed) {
+                res.fill(0);
+            }
 
             res
         } else {
@@ -334,6 +579,44 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
         }
     }
 
+    /// Carve a block for an allocation out of a free block.
+    ///
+    /// `block`, which must not be part of the pool, is aligned to `align` and split such that the
+    /// body is exactly `size` bytes. The front padding and the excessive tail are placed back in
+    /// the pool, and the body is returned.
+    ///
+    /// This is used by [`alloc`](#method.alloc) for the block found in the pool.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block` is too small to hold `size` bytes aligned to `align`.
+    fn satisfy(&mut self, mut block: Block, size: usize, align: usize) -> Block {
+        // Logging.
+        log!(self, "Carving {} bytes with alignment {} out of {:?}.", size, align, block);
+
+        let (aligner, body) = block.align(align).expect("The block is too small to be aligned.");
+        assert!(body.size() >= size, "The block is too small for the allocation.");
+        let (res, excessive) = body.split(size);
+
+        // Mark the padding and the excessive space as free. There are many corner cases that make
+        // knowing where to insert them difficult, so we search instead. The block is usually
+        // aligned already, in which case there is no padding to search for.
+        if !aligner.is_empty() {
+            let bound = self.find_bound(&aligner);
+            self.free_bound(bound, aligner);
+        }
+        let bound = self.find_bound(&excessive);
+        self.free_bound(bound, excessive);
+
+        // Check consistency.
+        self.check();
+        debug_assert!(res.aligned_to(align), "Alignment failed.");
+        debug_assert!(res.size() == size, "Requested space does not match with the returned \
+                      block.");
+
+        res
+    }
+
     /// Free a memory block.
     ///
     /// After this have been called, no guarantees are made about the passed pointer. If it want
@@ -377,15 +660,26 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
     /// And we're done. If it cannot be done, we insert the block, while keeping the list sorted.
     /// See [`insert`](#method.insert) for details.
     #[inline]
-    fn free(&mut self, block: Block) {
+    fn free(&mut self, mut block: Block) {
         // Just logging for the unlucky people debugging this shit. No problem.
         log!(self, "Freeing {:?}...", block);
 
+        // The caller might have poisoned the block already.
+        unpoison(&block);
+        scribble(&mut block);
+
         // Binary search for the block.
         let bound = self.find_bound(&block);
+        self.check_canaries(bound.start);
 
         // Free the given block.
         self.free_bound(bound, block);
+
+        // Make room for future insertions, while the pool is consistent.
+        self.pre_reserve();
+
+        // Let the allocator deal with the new memory.
+        self.on_new_memory();
     }
 
     /// Reallocate memory.
@@ -416,27 +710,48 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
     ///
     /// We simply find the block next to our initial block. If this block is free and have
     /// sufficient size, we will simply merge it into our initial block, and leave the excessive
-    /// space as free. If these conditions are not met, we have to allocate a new list, and then
-    /// deallocate the old one, after which we use memmove to copy the data over to the newly
+    /// space as free. Growing to the right keeps the start of the block, so the alignment of an
+    /// over-aligned block is preserved without realigning. This is skipped if the block is not
+    /// aligned to `align` already. If that fails, we try to grow the block backwards into the
+    /// block to the left (see [`realloc_left_bound`](#method.realloc_left_bound.html)), which
+    /// aligns the new start. If these conditions are not met, we have to allocate a new list, and
+    /// then deallocate the old one, after which we use memmove to copy the data over to the newly
     /// allocated list.
     fn realloc(&mut self, block: Block, new_size: usize, align: usize) -> Block {
+        // The caller might have poisoned (a part of) the block, which is moved or split.
+        unpoison(&block);
+
         // Find the index bound.
         let ind = self.find_bound(&block);
+        self.check_canaries(ind.start);
 
         // Logging.
         log!(self;ind, "Reallocating {:?} to size {} with align {}...", block, new_size, align);
 
-        // Try to do an inplace reallocation.
-        match self.realloc_inplace_bound(ind, block, new_size) {
+        // Try to do an inplace reallocation, and otherwise try to grow to the left. The alignment
+        // may be stricter than the one the block was allocated with, in which case the block
+        // cannot stay where it is.
+        let res = if block.aligned_to(align) {
+            self.realloc_inplace_bound(ind.clone(), block, new_size)
+        } else {
+            Err(block)
+        };
+        let res = match res {
+            Ok(block) => Ok(block),
+            Err(block) => self.realloc_left_bound(ind, block, new_size, align),
+        };
+
+        let res = match res {
             Ok(block) => block,
-            Err(block) => {
+            Err(mut block) => {
                 // Reallocation cannot be done inplace.
 
                 // Allocate a new block with the same size.
                 let mut res = self.alloc(new_size, align);
 
-                // Copy the old data to the new location.
-                block.copy_to(&mut res);
+                // Copy the old data to the new location. When shrinking, the data is truncated.
+                block.copy_prefix_to(&mut res, cmp::min(block.size(), new_size));
+                scribble(&mut block);
 
                 // Free the old block.
                 // Allocation may have moved insertion so we search again.
@@ -451,7 +766,13 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
 
                 res
             },
-        }
+        };
+
+        // Shrinking or moving the block frees memory, which the allocator must get to deal with,
+        // as with any free.
+        self.on_new_memory();
+
+        res
     }
 
     /// Extend/shrink the buffer inplace.
@@ -469,6 +790,9 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
         // Logging.
         log!(self, "Reallocating {:?} inplace to {}...", block, new_size);
 
+        // The caller might have poisoned (a part of) the block, which is split.
+        unpoison(&block);
+
         // Find the bounds of given block.
         let bound = self.find_bound(&block);
 
@@ -479,6 +803,9 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
         debug_assert!(res.as_ref().ok().map_or(true, |x| x.size() == new_size), "Requested space \
                       does not match with the returned block.");
 
+        // Shrinking frees the excessive space.
+        self.on_new_memory();
+
         res
     }
 
@@ -534,6 +861,8 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
                 if ind.start == self.pool.len() {
                     self.push(excessive);
                 } else if !excessive.is_empty() {
+                    self.total_bytes += excessive.size();
+                    poison(&excessive);
                     self.pool[ind.start] = excessive;
                 }
                 // Block will still not be adjacent, due to `excessive` being guaranteed to not be
@@ -549,6 +878,81 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
         Err(block)
     }
 
+    /// Grow a block on a known index bound backwards, into its left neighbor.
+    ///
+    /// If the block to the left is free and adjacent, the needed part of it is merged with the
+    /// block, and the data is moved to the new start of the block. This avoids relocating the
+    /// buffer when the right side is blocked, or too small on its own: if the block to the right
+    /// is free and adjacent too, it is absorbed as well. The new start is aligned to `align`.
+    ///
+    /// On failure, return `Err(Block)` with the old _intact_ block.
+    #[allow(cast_possible_wrap)]
+    fn realloc_left_bound(&mut self, ind: Range<usize>, mut block: Block, new_size: usize, align: usize)
+                          -> Result<Block, Block> {
+        // Logging.
+        log!(self;ind, "Try growing {:?} to the left to size {}.", block, new_size);
+
+        // Make sure that there is a left neighbor, and that it is adjacent to the block. Since the
+        // bound skips empty blocks, the left neighbor is not empty. A shrunk block might start to
+        // the right of the current start, outside the left neighbor, so shrinking is left to the
+        // caller.
+        if new_size < block.size() || ind.start == 0 || !self.pool[ind.start - 1].left_to(&block) {
+            return Err(block);
+        }
+
+        let old_ptr = *Pointer::from(block.empty_left());
+        let old_size = block.size();
+        let left_ptr = *Pointer::from(self.pool[ind.start - 1].empty_left()) as usize;
+
+        // The free block to the right, if any, can be absorbed as well.
+        let right_size = match self.pool.get(ind.end) {
+            Some(right) if block.left_to(right) => right.size(),
+            _ => 0,
+        };
+        let end = *Pointer::from(block.empty_right()) as usize + right_size;
+
+        // The end of the block is fixed, so we find the highest aligned start, which leaves room
+        // for `new_size` bytes.
+        if end - left_ptr < new_size {
+            return Err(block);
+        }
+        let start = end - new_size;
+        let start = start - start % cmp::max(align, 1);
+        if start < left_ptr {
+            return Err(block);
+        }
+
+        // Absorb the right neighbor. This does not move the left neighbor, as it is not empty.
+        if right_size != 0 {
+            block.merge_right(&mut self.remove_at(ind.end))
+                .expect("Unable to merge block with its right neighbor.");
+        }
+
+        // Take the needed part of the left neighbor and merge it with the block.
+        let (rest, mut res) = self.remove_at(ind.start - 1).split(start - left_ptr);
+        res.merge_right(&mut block).expect("Unable to merge block with its left neighbor.");
+
+        // Move the data to the new start. The two regions might overlap, hence we use memmove.
+        unsafe {
+            ptr::copy(old_ptr, *Pointer::from(res.empty_left()), old_size);
+        }
+
+        // Leave the excessive space.
+        let (res, excessive) = res.split(new_size);
+
+        // Put the remainders back.
+        let bound = self.find_bound(&rest);
+        self.free_bound(bound, rest);
+        let bound = self.find_bound(&excessive);
+        self.free_bound(bound, excessive);
+
+        // Check consistency.
+        self.check();
+        debug_assert!(res.aligned_to(align), "Alignment failed.");
+
+        Ok(res)
+    }
+
     /// Free a block placed in some index bound.
     ///
     /// This will at maximum insert one element.
@@ -570,6 +974,9 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
             return;
         }
 
+        fill_canary(&mut block);
+        poison(&block);
+
         // Assertions...
         debug_assert!(self.find(&block) == ind.start, "Block is not inserted at the appropriate \
                       index.");
@@ -581,16 +988,23 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
                 .expect("Unable to merge block right to the block at the end of the range");
 
             // The merging succeeded. We proceed to try to close in the possible gap.
+            let size = block.size();
             if ind.start != 0 && self.pool[ind.start - 1].merge_right(&mut block).is_ok() {
+                self.total_bytes += size;
                 self.check();
                 return;
             }
         // Dammit, let's try to merge left.
-        } else if ind.start != 0 && self.pool[ind.start - 1].merge_right(&mut block).is_ok() {
-            // Check consistency.
-            self.check();
+        } else {
+            let size = block.size();
+            if ind.start != 0 && self.pool[ind.start - 1].merge_right(&mut block).is_ok() {
+                self.total_bytes += size;
 
-            return;
+                // Check consistency.
+                self.check();
+
+                return;
+            }
         }
 
         // Well, it failed, so we insert it the old-fashioned way.
@@ -604,7 +1018,9 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
     ///
     /// "Fresh" means that the space is allocated through the breaker.
     ///
-    /// The returned pointer is guaranteed to be aligned to `align`.
+    /// The returned pointer is guaranteed to be aligned to `align`. The alignment padding of the
+    /// fresh space is pushed to the pool, where it merges into the last free block, if they are
+    /// adjacent (i.e. the top of the heap was free), rather than becoming a new entry.
     fn alloc_external(&mut self, size: usize, align: usize) -> Block {
         // Logging.
         log!(self, "Fresh allocation of size {} with alignment {}.", size, align);
@@ -625,13 +1041,19 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
 
         // Short-circuit in case on empty block.
         if !block.is_empty() {
+            fill_canary(&mut block);
+            poison(&block);
+
             // Some assertions...
             debug_assert!(self.pool.is_empty() || &block > self.pool.last().unwrap(), "Pushing will \
                           make the list unsorted.");
 
+            let size = block.size();
+
             // We will try to simply merge it with the last block.
             if let Some(x) = self.pool.last_mut() {
                 if x.merge_right(&mut block).is_ok() {
+                    self.total_bytes += size;
                     return;
                 }
             }
@@ -645,6 +1067,7 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
             // merge with. This has actually happened in testing.
             if let Some(x) = self.pool.last_mut() {
                 if x.merge_right(&mut block).is_ok() {
+                    self.total_bytes += size;
                     return;
                 }
             }
@@ -656,6 +1079,7 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
             if self.pool.is_empty() || &block > self.pool.last().unwrap() {
                 // We push.
                 let res = self.pool.push(block);
+                self.total_bytes += size;
 
                 // Make some assertions.
                 debug_assert!(res.is_ok(), "Push failed (buffer full).");
@@ -669,6 +1093,19 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
         self.check();
     }
 
+    /// Recover from an interrupted operation.
+    ///
+    /// When the OOM handler unwinds out of a reservation, the `reserving` flag is left set, which
+    /// would prevent the pool from ever growing again. This resets it.
+    ///
+    /// The pool itself is consistent, since fresh memory is only requested before it is modified.
+    /// The memory of the interrupted operation is leaked, though.
+    #[inline]
+    #[cfg(feature = "oom_unwind")]
+    pub fn recover(&mut self) {
+        self.reserving = false;
+    }
+
     /// Reserve some number of elements, and return the old buffer's block.
     ///
     /// # Assumptions
@@ -680,25 +1117,59 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
         log!(self;min_cap, "Reserving {}.", min_cap);
 
         if !self.reserving && (self.pool.capacity() < self.pool.len() + EXTRA_ELEMENTS || self.pool.capacity() < min_cap + EXTRA_ELEMENTS) {
+            #[cfg(test)]
+            unsafe { INLINE_GROWTHS += 1; }
+
             // Reserve a little extra for performance reasons.
-            let new_cap = (min_cap + EXTRA_ELEMENTS) * 2 + 16;
+            Some(self.grow((min_cap + EXTRA_ELEMENTS) * 2 + 16))
+        } else {
+            None
+        }
+    }
 
-            // Catch 'em all.
-            debug_assert!(new_cap > self.pool.capacity(), "Reserve shrinks?!");
+    /// Reserve capacity ahead of time, if the pool is getting full.
+    ///
+    /// This is called at a safe point, after an operation has completed, when the pool is more
+    /// than three quarters full. This way, inserting blocks in the middle of an operation (see
+    /// [`reserve`](#method.reserve)) does not have to grow the pool in the common case.
+    fn pre_reserve(&mut self) {
+        if !self.reserving && self.pool.len() * 4 >= self.pool.capacity() * 3 {
+            let new_cap = (self.pool.len() + EXTRA_ELEMENTS) * 2 + 16;
+            let old_buf = self.grow(new_cap);
 
-            // Make sure no unbounded reallocation happens.
-            self.reserving = true;
+            // The pool is now at most half full, so this will not reserve again.
+            self.free(old_buf);
+        }
+    }
 
-            // Break it to me!
-            let new_buf = self.alloc_external(new_cap * mem::size_of::<Block>(), mem::align_of::<Block>());
+    /// Grow the pool to a capacity of `new_cap` elements, and return the old buffer's block.
+    ///
+    /// # Assumptions
+    ///
+    /// This is assumed to not modify the order (see [`reserve`](#method.reserve)).
+    fn grow(&mut self, new_cap: usize) -> Block {
+        // Catch 'em all.
+        debug_assert!(new_cap > self.pool.capacity(), "Reserve shrinks?!");
 
-            // Go back to the original state.
-            self.reserving = false;
+        // Make sure no unbounded reallocation happens. Any reservation requested while this one is
+        // in progress (e.g. by `push`ing the aligner and excessive space of the fresh segment) is
+        // skipped, relying on the extra elements instead.
+        self.reserving = true;
 
-            Some(self.pool.refill(new_buf))
-        } else {
-            None
-        }
+        // Break it to me!
+        let new_buf = self.alloc_external(new_cap * mem::size_of::<Block>(), mem::align_of::<Block>());
+
+        // Go back to the original state.
+        self.reserving = false;
+
+        let old_buf = self.pool.refill(new_buf);
+
+        // Account the new buffer before the old one is released, so the count never underflows.
+        POOL_BYTES.fetch_add(self.pool.capacity() * mem::size_of::<Block>(),
+                             atomic::Ordering::Relaxed);
+        POOL_BYTES.fetch_sub(old_buf.size(), atomic::Ordering::Relaxed);
+
+        old_buf
     }
 
     /// Insert a block entry at some index.
@@ -827,6 +1298,7 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
                       // case where the closure is evaluated.
 
             // Set the element.
+            self.total_bytes += block.size();
             ptr::write(self.pool.get_unchecked_mut(ind), block);
         }
 
@@ -839,11 +1311,44 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
         self.check();
     }
 
+    /// Remove the top (rightmost) block of the pool.
+    ///
+    /// If the pool is empty, `None` is returned.
+    fn pop(&mut self) -> Option<Block> {
+        let len = self.pool.len();
+
+        if len == 0 {
+            None
+        } else {
+            // Since there are no trailing empty blocks, this is never empty.
+            let res = self.remove_at(len - 1);
+
+            // Check consistency.
+            self.check();
+
+            Some(res)
+        }
+    }
+
+    /// Remove the top block of the pool, if it ends at `end`.
+    ///
+    /// This is used for giving the free memory at the top of the program break back to the OS.
+    fn pop_top_at(&mut self, end: Pointer<u8>) -> Option<Block> {
+        if self.pool.last().map_or(false, |x| Pointer::from(x.empty_right()) == end) {
+            self.pop()
+        } else {
+            None
+        }
+    }
+
     /// Remove a block.
     fn remove_at(&mut self, ind: usize) -> Block {
         // Logging.
         log!(self;ind, "Removing block.");
 
+        // The block is leaving the pool.
+        self.total_bytes -= self.pool[ind].size();
+
         if ind + 1 == self.pool.len() {
             let res = self.pool[ind].pop();
             // Make sure there are no trailing empty blocks.
@@ -851,6 +1356,8 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
 
             // Truncate the vector.
             self.pool.truncate(new_len);
+
+            unpoison(&res);
             res
         } else {
             // Calculate the upper and lower bound
@@ -867,7 +1374,556 @@ pub trait Allocator: ops::DerefMut<Target = Bookkeeper> {
                 *place = empty2.empty_left();
             }
 
+            unpoison(&res);
+            res
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use prelude::*;
+
+    use super::*;
+
+    #[cfg(feature = "sanitizer")]
+    use sanitizer;
+
+    /// The alignment of the start of the fake program break.
+    ///
+    /// Aligning the start makes the layout independent of where the arena is placed, so the
+    /// offsets of blocks from the start are deterministic, even for aligned allocations.
+    const BRK_START_ALIGN: usize = 64;
+
+    /// A bookkeeper on top of a fake program break.
+    ///
+    /// Fresh memory is handed out from a fixed arena, just like SBRK extends the data segment.
+    struct TestAllocator {
+        /// The inner bookkeeper.
+        inner: Bookkeeper,
+        /// The unused part of the arena, starting at the fake program break.
+        brk: Block,
+        /// The start of the fake program break.
+        start: usize,
+        /// The number of times `on_new_memory` has been called.
+        new_memory: usize,
+        /// The arena, as a pointer and a size.
+        #[cfg(feature = "sanitizer")]
+        arena: (*const u8, usize),
+    }
+
+    impl TestAllocator {
+        /// Create a new allocator on top of some arena.
+        fn new(arena: &mut [usize]) -> TestAllocator {
+            let brk = unsafe {
+                Block::from_raw_parts(Pointer::new(arena.as_mut_ptr() as *mut u8),
+                                      arena.len() * mem::size_of::<usize>())
+            };
+            let (pool, mut rest) = brk.split(4 * EXTRA_ELEMENTS * mem::size_of::<Block>());
+            let (_, brk) = rest.align(BRK_START_ALIGN).expect("The arena is too small.");
+
+            TestAllocator {
+                inner: Bookkeeper::new(unsafe { Vec::from_raw_parts(pool, 0) }),
+                start: *Pointer::from(brk.empty_left()) as usize,
+                brk: brk,
+                new_memory: 0,
+                #[cfg(feature = "sanitizer")]
+                arena: (arena.as_ptr() as *const u8, arena.len() * mem::size_of::<usize>()),
+            }
+        }
+
+        /// Get the offset of a block from the start of the fake program break.
+        fn offset(&self, block: &Block) -> usize {
+            *Pointer::from(block.empty_left()) as usize - self.start
+        }
+
+        /// Get the free blocks as `(offset, size)` pairs.
+        ///
+        /// Empty blocks are skipped.
+        fn free_list(&self) -> ([(usize, usize); 8], usize) {
+            let mut res = [(0, 0); 8];
+            let mut len = 0;
+
+            for block in self.pool.iter().filter(|x| !x.is_empty()) {
+                res[len] = (self.offset(block), block.size());
+                len += 1;
+            }
+
+            (res, len)
+        }
+
+        /// Move the fake program break without giving the memory to the allocator.
+        ///
+        /// This simulates some other code using SBRK.
+        fn gap(&mut self, size: usize) {
+            let (_, brk) = self.brk.pop().split(size);
+            self.brk = brk;
+        }
+    }
+
+    impl ops::Deref for TestAllocator {
+        type Target = Bookkeeper;
+
+        fn deref(&self) -> &Bookkeeper {
+            &self.inner
+        }
+    }
+
+    impl ops::DerefMut for TestAllocator {
+        fn deref_mut(&mut self) -> &mut Bookkeeper {
+            &mut self.inner
+        }
+    }
+
+    impl Drop for TestAllocator {
+        fn drop(&mut self) {
+            // The pool lives in the arena, so it stops counting toward the pool bytes.
+            POOL_BYTES.fetch_sub(self.pool.capacity() * mem::size_of::<Block>(),
+                                 atomic::Ordering::Relaxed);
+
+            // The arena lives on the stack, which is reused after the test, so it must not stay
+            // poisoned.
+            #[cfg(feature = "sanitizer")]
+            sanitizer::allocated(self.arena.0, self.arena.1, 0);
+        }
+    }
+
+    impl Allocator for TestAllocator {
+        fn alloc_fresh(&mut self, size: usize, align: usize) -> Block {
+            let (aligner, rest) = self.brk.align(align).expect("The arena is exhausted.");
+            let (res, brk) = rest.split(size);
+            self.brk = brk;
+
+            // The aligner is above every block in the pool, so this does not change the order.
+            self.push(aligner);
+
             res
         }
+
+        fn on_new_memory(&mut self) {
+            self.new_memory += 1;
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(feature = "paranoid")]
+    fn test_paranoid_neighbor() {
+        let mut arena = [0; 1024];
+        let mut alloc = TestAllocator::new(&mut arena);
+
+        let a = alloc.alloc(32, 1);
+        let _b = alloc.alloc(32, 1);
+        let c = alloc.alloc(32, 1);
+
+        let ptr = *Pointer::from(a.empty_left());
+        alloc.free(a);
+
+        // Write to `a` after freeing it.
+        unsafe {
+            *ptr.offset(3) = 0;
+        }
+
+        // Freeing the unrelated `c` checks its neighbor in the pool, `a`.
+        alloc.free(c);
+    }
+
+    #[test]
+    fn test_align_padding_merges_left() {
+        let mut arena = [0; 1024];
+        let mut alloc = TestAllocator::new(&mut arena);
+
+        let a = alloc.alloc(8, 1);
+        let a_ptr = *Pointer::from(a.empty_left());
+        alloc.free(a);
+        assert_eq!(alloc.pool.len(), 1);
+
+        // `a` does not fit, so this is served from fresh memory, whose padding is right after
+        // `a`.
+        let b = alloc.alloc(64, 64);
+        assert!(b.aligned_to(64));
+
+        // The padding merged into `a` instead of creating a new entry.
+        assert_eq!(alloc.pool.len(), 1);
+        assert_eq!(*Pointer::from(alloc.pool[0].empty_left()), a_ptr);
+        assert!(alloc.pool[0].left_to(&b));
+    }
+
+    #[test]
+    fn test_largest_free() {
+        let mut arena = [0; 512];
+        let mut alloc = TestAllocator::new(&mut arena);
+        assert_eq!(alloc.largest_free(), 0);
+
+        let a = alloc.alloc(16, 1);
+        let _b = alloc.alloc(8, 1);
+        let c = alloc.alloc(64, 1);
+        let _d = alloc.alloc(8, 1);
+
+        alloc.free(a);
+        alloc.free(c);
+
+        assert_eq!(alloc.largest_free(), 64);
+    }
+
+    #[test]
+    fn test_merge_across_classes() {
+        let mut arena = [0; 512];
+        let mut alloc = TestAllocator::new(&mut arena);
+
+        let a = alloc.alloc(16, 1);
+        let b = alloc.alloc(200, 1);
+        let _c = alloc.alloc(8, 1);
+        assert!(Block::size_class(a.size()) != Block::size_class(b.size()));
+
+        alloc.free(a);
+        alloc.free(b);
+
+        // The pool is ordered by address, not by size class, so adjacent blocks are merged
+        // regardless of their classes.
+        let (blocks, len) = alloc.free_list();
+        assert_eq!(&blocks[..len], &[(0, 216)]);
+
+        // The merged block serves an allocation too large for either of the two blocks.
+        let d = alloc.alloc(216, 1);
+        assert_eq!(alloc.offset(&d), 0);
+        assert_eq!(alloc.free_list().1, 0);
+    }
+
+    #[test]
+    fn test_search_bound() {
+        let mut arena = [0; 1024];
+        let mut alloc = TestAllocator::new(&mut arena);
+
+        // Leave six small free blocks, separated by live ones, in front of a large free block.
+        for _ in 0..6 {
+            let (small, _live) = alloc.alloc(16, 1).split(8);
+            alloc.free(small);
+        }
+        let large = alloc.alloc(128, 1);
+        let _end = alloc.alloc(8, 1);
+        alloc.free(large);
+        assert_eq!(alloc.free_list().1, 7);
+
+        // The large block is not within the bound, so nothing fits.
+        assert!(alloc.first_fit(64, 1, 6).is_none());
+        assert_eq!(alloc.free_list().1, 7);
+
+        // Without a bound, the large block is found.
+        let res = alloc.alloc(64, 1);
+        assert_eq!(alloc.offset(&res), 96);
+    }
+
+    #[test]
+    fn test_deterministic_layout() {
+        let mut arena = [0; 512];
+        let mut alloc = TestAllocator::new(&mut arena);
+
+        let a = alloc.alloc(16, 1);
+        let b = alloc.alloc(32, 1);
+        let c = alloc.alloc(8, 1);
+        assert_eq!(alloc.offset(&a), 0);
+        assert_eq!(alloc.offset(&b), 16);
+        assert_eq!(alloc.offset(&c), 48);
+
+        alloc.free(a);
+        // `c` blocks the right side, so `b` grows into `a`.
+        let b = alloc.realloc(b, 40, 1);
+        assert_eq!(alloc.offset(&b), 8);
+        let (list, len) = alloc.free_list();
+        assert_eq!(&list[..len], &[(0, 8)]);
+
+        alloc.free(c);
+        let (list, len) = alloc.free_list();
+        assert_eq!(&list[..len], &[(0, 8), (48, 8)]);
+
+        // The first block is aligned (the start is), so it is used whole.
+        let d = alloc.alloc(8, 16);
+        assert_eq!(alloc.offset(&d), 0);
+        let (list, len) = alloc.free_list();
+        assert_eq!(&list[..len], &[(48, 8)]);
+    }
+
+    #[test]
+    fn test_pre_reserve() {
+        let mut arena = [0; 1024];
+        let mut alloc = TestAllocator::new(&mut arena);
+
+        let mut rest = alloc.alloc(48 * 16, 1);
+        let growths = unsafe { INLINE_GROWTHS };
+
+        // Free every other piece, such that no free blocks are adjacent.
+        for _ in 0..24 {
+            let (a, tail) = rest.split(16);
+            let (_, tail2) = tail.split(16);
+            rest = tail2;
+
+            alloc.free(a);
+        }
+
+        // The pool grew only at the safe points after the frees.
+        assert_eq!(unsafe { INLINE_GROWTHS }, growths);
+        assert!(alloc.pool.len() >= 24);
+        assert!(alloc.pool.len() * 4 < alloc.pool.capacity() * 3);
+    }
+
+    #[test]
+    #[cfg(feature = "sanitizer")]
+    fn test_pool_poisoned() {
+        let mut arena = [0; 1024];
+        let mut alloc = TestAllocator::new(&mut arena);
+
+        let mut rest = alloc.alloc(48 * 16, 1);
+
+        // Free every other piece, such that the pool grows into memory, which was free before.
+        for _ in 0..24 {
+            let (mut a, tail) = rest.split(16);
+            let (_, tail2) = tail.split(16);
+            rest = tail2;
+
+            a.fill(0);
+            alloc.free(a);
+        }
+
+        // The free blocks are poisoned, while the buffer of the pool is not.
+        for block in alloc.pool.iter().filter(|x| !x.is_empty()) {
+            assert!(sanitizer::is_poisoned(*Pointer::from(block.empty_left()), block.size()));
+        }
+        assert!(!sanitizer::is_poisoned(alloc.pool.as_ptr() as *const u8,
+                                        alloc.pool.capacity() * mem::size_of::<Block>()));
+
+        // Reuse the freed memory, both through the allocation and the reallocation paths. Every
+        // write of ralloc itself is checked to hit unpoisoned memory.
+        let mut a = alloc.alloc(16, 1);
+        a.fill(1);
+        let b = alloc.alloc(16, 1);
+        let mut b = alloc.realloc(b, 200, 1);
+        b.fill(2);
+        let a = alloc.realloc(a, 300, 1);
+        assert!(!sanitizer::is_poisoned(*Pointer::from(a.empty_left()), 300));
+    }
+
+    #[test]
+    #[cfg(feature = "log")]
+    fn test_alignment_waste_warning() {
+        let mut arena = [0; 2048];
+        let mut alloc = TestAllocator::new(&mut arena);
+
+        let warnings = unsafe { ALIGNMENT_WARNINGS };
+        let _ = alloc.alloc(16, 8);
+        assert_eq!(unsafe { ALIGNMENT_WARNINGS }, warnings);
+
+        let _ = alloc.alloc(1, 4096);
+        assert_eq!(unsafe { ALIGNMENT_WARNINGS }, warnings + 1);
+    }
+
+    #[test]
+    fn test_realloc_left() {
+        let mut arena = [0; 512];
+        let mut alloc = TestAllocator::new(&mut arena);
+
+        let a = alloc.alloc(16, 1);
+        let mut b = alloc.alloc(16, 1);
+        // Block the right side.
+        let _c = alloc.alloc(16, 1);
+
+        let a_ptr = *Pointer::from(a.empty_left()) as usize;
+        let b_ptr = *Pointer::from(b.empty_left()) as usize;
+        for i in 0..16 {
+            b.set(i, i as u8);
+        }
+
+        alloc.free(a);
+        let b = alloc.realloc(b, 24, 1);
+
+        // The block should have grown into `a`, moving its base pointer to the left.
+        assert_eq!(b.size(), 24);
+        assert_eq!(*Pointer::from(b.empty_left()) as usize, a_ptr + 8);
+        assert!((*Pointer::from(b.empty_left()) as usize) < b_ptr);
+        for i in 0..16 {
+            assert_eq!(b.get(i), i as u8);
+        }
+
+        // The rest of `a` is still free.
+        assert_eq!(alloc.total_bytes(), 8);
+    }
+
+    #[test]
+    fn test_realloc_misaligned_shrink() {
+        let mut arena = [0; 512];
+        let mut alloc = TestAllocator::new(&mut arena);
+
+        let x = alloc.alloc(300, 1);
+        let mut a = alloc.alloc(100, 1);
+        // Block the right side.
+        let _c = alloc.alloc(16, 1);
+        for i in 0..100 {
+            a.set(i, i as u8);
+        }
+
+        // `a` is misaligned, and its new start would not be in the free block to its left.
+        alloc.free(x);
+        assert!(alloc.offset(&a) % 8 != 0);
+        let a = alloc.realloc(a, 50, 8);
+
+        // The block is relocated, keeping the prefix.
+        assert_eq!(a.size(), 50);
+        assert!(a.aligned_to(8));
+        for i in 0..50 {
+            assert_eq!(a.get(i), i as u8);
+        }
+
+        // The same goes without a free block to the left.
+        let mut b = alloc.alloc(100, 1);
+        for i in 0..100 {
+            b.set(i, i as u8);
+        }
+        assert!(a.left_to(&b));
+        let b = alloc.realloc(b, 50, 256);
+
+        assert_eq!(b.size(), 50);
+        assert!(b.aligned_to(256));
+        for i in 0..50 {
+            assert_eq!(b.get(i), i as u8);
+        }
+    }
+
+    #[test]
+    fn test_realloc_both_sides() {
+        let mut arena = [0; 512];
+        let mut alloc = TestAllocator::new(&mut arena);
+
+        let a = alloc.alloc(16, 1);
+        let mut b = alloc.alloc(16, 1);
+        let c = alloc.alloc(16, 1);
+        // Block the right side.
+        let _d = alloc.alloc(16, 1);
+
+        for i in 0..16 {
+            b.set(i, i as u8);
+        }
+
+        alloc.free(a);
+        alloc.free(c);
+        // Neither `a` nor `c` suffices on its own.
+        let b = alloc.realloc(b, 40, 1);
+
+        // The block should have grown into both `a` and `c`.
+        assert_eq!(b.size(), 40);
+        assert_eq!(alloc.offset(&b), 8);
+        for i in 0..16 {
+            assert_eq!(b.get(i), i as u8);
+        }
+
+        let (list, len) = alloc.free_list();
+        assert_eq!(&list[..len], &[(0, 8)]);
+    }
+
+    #[test]
+    fn test_realloc_new_memory() {
+        let mut arena = [0; 512];
+        let mut alloc = TestAllocator::new(&mut arena);
+
+        let a = alloc.alloc(16, 1);
+        // Block the right side of `a`.
+        let _b = alloc.alloc(16, 1);
+
+        // Moving the buffer frees the old one.
+        let a = alloc.realloc(a, 64, 1);
+        assert_eq!(alloc.new_memory, 1);
+
+        // Shrinking frees the excessive space.
+        let a = alloc.realloc(a, 32, 1);
+        assert_eq!(alloc.new_memory, 2);
+        assert!(alloc.realloc_inplace(a, 16).is_ok());
+        assert_eq!(alloc.new_memory, 3);
+    }
+
+    #[test]
+    fn test_realloc_aligned_inplace() {
+        let mut arena = [0; 1024];
+        let mut alloc = TestAllocator::new(&mut arena);
+
+        // `a` ends at an aligned address, so `b` follows it directly, whatever the alignment of
+        // the fake program break. No address in the aligner of `a` is aligned, so it cannot hold
+        // `b`.
+        let a = alloc.alloc(64, 64);
+        let b = alloc.alloc(32, 64);
+        // Block the right side of `b`.
+        let _c = alloc.alloc(16, 1);
+
+        let a_ptr = *Pointer::from(a.empty_left()) as usize;
+        assert!(a.left_to(&b));
+
+        alloc.free(b);
+        let a = alloc.realloc(a, 80, 64);
+
+        // The block should have grown into `b` without moving.
+        assert_eq!(a.size(), 80);
+        assert_eq!(*Pointer::from(a.empty_left()) as usize, a_ptr);
+        assert!(a.aligned_to(64));
+    }
+
+    #[test]
+    fn test_satisfy() {
+        let mut arena = [0; 1024];
+        let mut alloc = TestAllocator::new(&mut arena);
+
+        // Get a block, which is not aligned to 32, right after a free block.
+        let block = alloc.alloc(64, 32);
+        let start = *Pointer::from(block.empty_left()) as usize;
+        let (pre, block) = block.split(1);
+        alloc.free(pre);
+
+        let before = alloc.total_bytes();
+        let res = alloc.satisfy(block, 16, 32);
+
+        assert_eq!(res.size(), 16);
+        assert_eq!(*Pointer::from(res.empty_left()) as usize, start + 32);
+
+        // The 31 bytes of padding and the 16 bytes of tail are back in the pool.
+        assert_eq!(alloc.total_bytes(), before + 31 + 16);
+    }
+
+    #[test]
+    fn test_pop_top_at() {
+        let mut arena = [0; 512];
+        let mut alloc = TestAllocator::new(&mut arena);
+        let start = Pointer::from(alloc.brk.empty_left());
+
+        let a = alloc.alloc(16, 1);
+        let b = alloc.alloc(32, 1);
+
+        // `b` is still in use, so nothing can be released.
+        alloc.free(a);
+        assert!(alloc.pop_top_at(Pointer::from(alloc.brk.empty_left())).is_none());
+
+        alloc.free(b);
+        let mut top = alloc.pop_top_at(Pointer::from(alloc.brk.empty_left()))
+            .expect("The top block was not released.");
+
+        // Give it back to the fake program break, which should then be back at the baseline.
+        top.merge_right(&mut alloc.brk).unwrap();
+        alloc.brk = top;
+        assert!(Pointer::from(alloc.brk.empty_left()) == start);
+        assert_eq!(alloc.total_bytes(), 0);
+    }
+
+    #[test]
+    fn test_brk_gap() {
+        let mut arena = [0; 512];
+        let mut alloc = TestAllocator::new(&mut arena);
+
+        let a = alloc.alloc(16, 1);
+        alloc.gap(8);
+        let b = alloc.alloc(16, 1);
+        assert!(!a.left_to(&b));
+
+        alloc.free(a);
+        alloc.free(b);
+
+        // The blocks must not be merged across the gap.
+        assert_eq!(alloc.pool.len(), 2);
+        assert_eq!(alloc.total_bytes(), 32);
     }
 }
@@ -5,6 +5,64 @@
 use prelude::*;
 
 use core::cmp;
+use core::sync::atomic::{self, AtomicUsize, AtomicBool};
+
+use fail;
+
+/// The alignment of the start of the heap.
+///
+/// The first segment is aligned to this, such that allocations with common alignments can be
+/// served from the start of the heap without padding.
+const HEAP_ALIGN: usize = 16;
+
+/// Has the start of the heap been aligned?
+static BASE_ALIGNED: AtomicBool = AtomicBool::new(false);
+
+/// The lowest address of the segments acquired through [`get`](fn.get.html) (`!0` if none).
+static HEAP_START: AtomicUsize = AtomicUsize::new(!0);
+/// The highest end of the segments acquired through [`get`](fn.get.html) (0 if none).
+static HEAP_END: AtomicUsize = AtomicUsize::new(0);
+
+/// Extend the tracked heap range to include `block`.
+fn track(block: &Block) {
+    let start = *Pointer::from(block.empty_left()) as usize;
+    let end = *Pointer::from(block.empty_right()) as usize;
+
+    let mut old = HEAP_START.load(atomic::Ordering::Relaxed);
+    while start < old {
+        match HEAP_START.compare_and_swap(old, start, atomic::Ordering::Relaxed) {
+            x if x == old => break,
+            x => old = x,
+        }
+    }
+    let mut old = HEAP_END.load(atomic::Ordering::Relaxed);
+    while end > old {
+        match HEAP_END.compare_and_swap(old, end, atomic::Ordering::Relaxed) {
+            x if x == old => break,
+            x => old = x,
+        }
+    }
+}
+
+/// Does `ptr` lie within the range of the segments acquired so far?
+///
+/// This is a range check, which is conservative: the break can be moved by others, so memory
+/// between the segments might not belong to ralloc.
+#[inline]
+pub fn in_heap(ptr: *const u8) -> bool {
+    let ptr = ptr as usize;
+
+    HEAP_START.load(atomic::Ordering::Relaxed) <= ptr && ptr < HEAP_END.load(atomic::Ordering::Relaxed)
+}
+
+/// Discard the leading bytes of a segment, which are not aligned to `HEAP_ALIGN`.
+///
+/// The discarded bytes are leaked, so this is only done once, for the start of the heap.
+fn align_base(mut segment: Block) -> Block {
+    let (_, rest) = segment.align(HEAP_ALIGN).expect("The segment is too small to be aligned.");
+
+    rest
+}
 
 /// Canonicalize a BRK request.
 ///
@@ -42,13 +100,32 @@ fn canonicalize_space(min: usize) -> usize {
 /// The first block represents the aligner segment (that is the precursor aligning the middle
 /// block to `align`), the second one is the result and is of exactly size `size`. The last
 /// block is the excessive space.
+///
+/// If the program break cannot be extended by the canonicalized size, it is retried once without
+/// the extra space, since it might be the extra space pushing it over the limit.
+///
+/// The start of the first segment is aligned to `HEAP_ALIGN`, by discarding the bytes before it.
 pub fn get(size: usize, align: usize) -> (Block, Block, Block) {
+    // The first segment gets room for aligning the start of the heap.
+    let first = !BASE_ALIGNED.swap(true, atomic::Ordering::Relaxed);
+    let base_padding = if first { HEAP_ALIGN } else { 0 };
+
     // Calculate the canonical size (extra space is allocated to limit the number of system calls).
-    let brk_size = canonicalize_space(size) + align;
+    let canonical_size = canonicalize_space(size) + align + base_padding;
+
+    // Use SBRK to allocate extra data segment.
+    let mut brk = Block::try_brk(canonical_size)
+        .or_else(|()| Block::try_brk(size + align + base_padding))
+        .unwrap_or_else(|()| fail::oom());
+    if first {
+        brk = align_base(brk);
+    }
+    let brk_size = brk.size();
+    track(&brk);
 
-    // Use SBRK to allocate extra data segment. The alignment is used as precursor for our
-    // allocated block. This ensures that it is properly memory aligned to the requested value.
-    let (alignment_block, rest) = Block::brk(brk_size).align(align).unwrap();
+    // The alignment is used as precursor for our allocated block. This ensures that it is
+    // properly memory aligned to the requested value.
+    let (alignment_block, rest) = brk.align(align).unwrap();
 
     // Split the block to leave the excessive space.
     let (res, excessive) = rest.split(size);
@@ -64,6 +141,8 @@ pub fn get(size: usize, align: usize) -> (Block, Block, Block) {
 mod test {
     use super::*;
 
+    use {block, sys};
+
     #[test]
     fn test_ordered() {
         let brk = get(20, 1);
@@ -71,4 +150,55 @@ mod test {
         assert!(brk.0 <= brk.1);
         assert!(brk.1 <= brk.2);
     }
+
+    #[test]
+    fn test_in_heap() {
+        let (_, res, _) = get(20, 1);
+        let ptr = *Pointer::from(res) as *const u8;
+
+        assert!(in_heap(ptr));
+        assert!(!in_heap(&ptr as *const _ as *const u8));
+    }
+
+    #[test]
+    fn test_align_base() {
+        let mut arr = [0u8; 64];
+        let start = &mut arr[0] as *mut u8;
+
+        // Fake a segment starting at a misaligned break.
+        let (ptr, aligner) = block::align_pointer(start, HEAP_ALIGN);
+        let segment = unsafe {
+            Block::from_raw_parts(Pointer::new(ptr.offset(1)), 64 - aligner - 1)
+        };
+
+        let base = align_base(segment);
+        assert!(base.aligned_to(HEAP_ALIGN));
+        assert_eq!(base.size(), 64 - aligner - HEAP_ALIGN);
+
+        // Allocations with the base alignment need no padding.
+        let (mut base, _) = base.split(HEAP_ALIGN * 2);
+        let (padding, _) = base.align(HEAP_ALIGN).unwrap();
+        assert!(padding.is_empty());
+    }
+
+    #[test]
+    fn test_retry_exact() {
+        // Make sure that the start of the heap is aligned already, as it needs extra space.
+        get(20, 1);
+
+        // Make the canonicalized request fail, but let the exact one through.
+        unsafe {
+            sys::SBRK_LIMIT = 5001;
+        }
+
+        let (aligner, res, excessive) = get(5000, 1);
+
+        unsafe {
+            sys::SBRK_LIMIT = !0;
+        }
+
+        assert!(aligner.is_empty());
+        assert_eq!(res.size(), 5000);
+        assert_eq!(excessive.size(), 1);
+    }
 }
@@ -0,0 +1,144 @@
+//! Bump allocation mode.
+//!
+//! Some phases of a program (e.g. startup) allocate a lot, and free nothing until a clear
+//! boundary. In bump mode, the allocations of a thread are served from an `Arena`, and freed all
+//! at once when the mode ends.
+
+use arena::Arena;
+
+/// The minimum size of the chunks of a bump region.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The bump region of this thread, or `None` if bump mode is not active.
+///
+/// The arena is taken out while it is in use, such that the chunks it acquires are allocated
+/// normally.
+#[thread_local]
+static mut REGION: Option<Arena> = None;
+
+/// Enter bump mode on this thread.
+///
+/// Until [`end_bump`](fn.end_bump.html) is called, every allocation made through `alloc` and
+/// `realloc` on this thread is served by bumping a pointer through a dedicated region, bypassing
+/// the pools. Freeing these buffers does nothing, and buffers cannot grow inplace. Buffers
+/// allocated before are freed and reallocated (into the region) as usual.
+///
+/// Tagged allocations (`alloc_tagged` and `realloc_tagged`, with the `stats` feature) are not
+/// affected, and stay out of the region.
+///
+/// Calling this while bump mode is active already does nothing.
+///
+/// The region is only freed by `end_bump`. If the thread exits in bump mode, the region is leaked,
+/// since thread-locals are not destructed.
+///
+/// # Safety
+///
+/// Buffers allocated in bump mode must not be freed or reallocated on other threads, since only
+/// this thread knows about the region. The other threads would give them to their allocator as
+/// normal buffers, corrupting it.
+#[inline]
+pub unsafe fn begin_bump() {
+    if REGION.is_none() {
+        REGION = Some(Arena::new(CHUNK_SIZE));
+    }
+}
+
+/// Leave bump mode on this thread, freeing the region at once.
+///
+/// # Safety
+///
+/// Every buffer allocated in bump mode becomes invalid. They must not be used (or freed)
+/// afterwards.
+#[inline]
+pub unsafe fn end_bump() {
+    // Take the region out before dropping it, so its chunks are freed normally.
+    drop(REGION.take());
+}
+
+/// Is bump mode active on this thread?
+#[inline]
+pub fn is_active() -> bool {
+    unsafe { REGION.is_some() }
+}
+
+/// Allocate from the bump region, if bump mode is active.
+#[inline]
+pub fn alloc(size: usize, align: usize) -> Option<*mut u8> {
+    unsafe {
+        match REGION.take() {
+            Some(mut region) => {
+                let ptr = region.alloc(size, align);
+                REGION = Some(region);
+
+                Some(ptr)
+            },
+            None => None,
+        }
+    }
+}
+
+/// Was `ptr` allocated from the bump region of this thread?
+#[inline]
+pub fn contains(ptr: *const u8) -> bool {
+    unsafe {
+        REGION.as_ref().map_or(false, |region| region.contains(ptr))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use allocator;
+    use arena;
+
+    #[test]
+    fn test_bump() {
+        let before = allocator::alloc(16, 8);
+
+        unsafe { begin_bump(); }
+        assert!(is_active());
+
+        let mut ptrs = [0 as *mut u8; 256];
+        for (n, ptr) in ptrs.iter_mut().enumerate() {
+            *ptr = allocator::alloc(24, 8);
+            assert!(contains(*ptr));
+            assert_eq!(*ptr as usize % 8, 0);
+
+            unsafe {
+                **ptr = n as u8;
+            }
+        }
+
+        unsafe {
+            // Freeing does nothing, so the buffer is still intact.
+            allocator::free(ptrs[0], 24);
+            assert_eq!(*ptrs[0], 0);
+
+            // Reallocation moves the buffer within the region.
+            let ptr = allocator::realloc(ptrs[1], 24, 4096, 8);
+            assert!(contains(ptr));
+            assert_eq!(*ptr, 1);
+
+            // Buffers allocated before are not in the region.
+            assert!(!contains(before));
+            allocator::free(before, 16);
+
+            let released = arena::RELEASED_CHUNKS;
+            end_bump();
+
+            // The chunk of the region was given back.
+            assert_eq!(arena::RELEASED_CHUNKS, released + 1);
+        }
+
+        assert!(!is_active());
+        assert!(!contains(ptrs[2]));
+
+        // Normal allocation resumes.
+        let ptr = allocator::alloc(24, 8);
+        assert!(!contains(ptr));
+        unsafe {
+            allocator::free(ptr, 24);
+        }
+    }
+}
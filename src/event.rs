@@ -0,0 +1,126 @@
+//! Allocation events.
+//!
+//! This forwards every allocation, reallocation, and free to a user callback, which can be used
+//! for live profiling outside the crate.
+
+use core::mem;
+use core::sync::atomic::{self, AtomicUsize};
+
+/// An allocation event.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AllocEvent {
+    /// A buffer was allocated.
+    Alloc {
+        /// The pointer to the buffer.
+        ptr: *mut u8,
+        /// The size of the buffer.
+        size: usize,
+        /// The requested alignment.
+        align: usize,
+    },
+    /// A buffer was freed.
+    Free {
+        /// The pointer to the buffer.
+        ptr: *mut u8,
+        /// The size of the buffer.
+        size: usize,
+    },
+    /// A buffer was reallocated.
+    Realloc {
+        /// The pointer to the old buffer.
+        old: *mut u8,
+        /// The pointer to the new buffer.
+        ///
+        /// This is `old`, if the buffer was resized inplace.
+        new: *mut u8,
+        /// The size of the new buffer.
+        size: usize,
+    },
+}
+
+/// The event callback, as an address, or 0 if there is none.
+static CALLBACK: AtomicUsize = AtomicUsize::new(0);
+
+/// Set the callback, which is called on every allocation event.
+///
+/// The callback is called after every allocation, reallocation (inplace or not), and free,
+/// including the tagged variants, on the thread doing the operation, with no allocator locked. A
+/// failed inplace reallocation changes nothing, and sends no event. `None` removes the callback,
+/// after which an event costs only a load.
+///
+/// The callback may allocate through ralloc, but that generates events of its own, which reenter
+/// the callback. It must therefore not assume that events are delivered one at a time, even on a
+/// single thread, and must bound its own allocation to avoid unbounded recursion.
+#[inline]
+pub fn set_event_callback(callback: Option<fn(AllocEvent)>) {
+    CALLBACK.store(callback.map_or(0, |x| x as usize), atomic::Ordering::Relaxed);
+}
+
+/// Send an event to the callback, if any.
+#[inline]
+pub fn emit(event: AllocEvent) {
+    let callback = CALLBACK.load(atomic::Ordering::Relaxed);
+
+    if callback != 0 {
+        unsafe { mem::transmute::<usize, fn(AllocEvent)>(callback)(event) };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use allocator;
+
+    /// The events seen on this thread.
+    #[thread_local]
+    static mut EVENTS: [Option<AllocEvent>; 8] = [None; 8];
+    /// The number of events seen on this thread.
+    #[thread_local]
+    static mut LEN: usize = 0;
+
+    fn record(event: AllocEvent) {
+        unsafe {
+            if LEN < EVENTS.len() {
+                EVENTS[LEN] = Some(event);
+                LEN += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_events() {
+        set_event_callback(Some(record));
+
+        unsafe {
+            let ptr = allocator::alloc(16, 8);
+            let new = allocator::realloc(ptr, 16, 32, 8);
+            allocator::realloc_inplace(new, 32, 24).unwrap();
+            allocator::free(new, 24);
+
+            #[cfg(feature = "stats")]
+            let tagged = allocator::alloc_tagged(8, 1, 1);
+            #[cfg(feature = "stats")]
+            allocator::free_tagged(tagged, 8, 1);
+
+            set_event_callback(None);
+            // No more events are sent.
+            allocator::free(allocator::alloc(8, 1), 8);
+
+            assert_eq!(&EVENTS[..4], &[
+                Some(AllocEvent::Alloc { ptr: ptr, size: 16, align: 8 }),
+                Some(AllocEvent::Realloc { old: ptr, new: new, size: 32 }),
+                Some(AllocEvent::Realloc { old: new, new: new, size: 24 }),
+                Some(AllocEvent::Free { ptr: new, size: 24 }),
+            ]);
+
+            #[cfg(feature = "stats")]
+            assert_eq!(&EVENTS[4..LEN], &[
+                Some(AllocEvent::Alloc { ptr: tagged, size: 8, align: 1 }),
+                Some(AllocEvent::Free { ptr: tagged, size: 8 }),
+            ]);
+            #[cfg(not(feature = "stats"))]
+            assert_eq!(LEN, 4);
+        }
+    }
+}
@@ -7,6 +7,10 @@ use core::{mem, intrinsics};
 
 #[cfg(feature = "tls")]
 use tls;
+#[cfg(feature = "debug_tools")]
+use {trace, write};
+#[cfg(feature = "oom_unwind")]
+use allocator;
 
 /// The global OOM handler.
 static OOM_HANDLER: AtomicPtr<()> = AtomicPtr::new(default_oom_handler as *mut ());
@@ -38,7 +42,27 @@ fn default_oom_handler() -> ! {
 ///
 /// The rule of thumb is that this should be called, if and only if unwinding (which allocates)
 /// will hit the same error.
+///
+/// # Unwinding
+///
+/// With the `oom_unwind` feature, the OOM handler is allowed to unwind (i.e. panic) to a recovery
+/// point, after which the allocator is usable again. For this to work, the lock of the global
+/// allocator is released before the handler is called, so the handler (and the panic machinery,
+/// which allocates) can reenter the allocator. The interrupted allocation is abandoned.
+///
+/// The rest of the allocator's state is restored by destructors while unwinding. Recovering
+/// without unwinding (e.g. through `longjmp`) skips these, and is not supported.
 pub fn oom() -> ! {
+    // Dump the recent allocation events, to make it easier to find out what went wrong.
+    #[cfg(feature = "debug_tools")]
+    {
+        let _ = trace::dump_recent_events(&mut write::LogWriter::new());
+    }
+
+    // Let the handler reenter the allocator.
+    #[cfg(feature = "oom_unwind")]
+    allocator::release_global_lock();
+
     // If TLS is enabled, we will use the thread-local OOM.
     #[cfg(feature = "tls")]
     {
@@ -55,7 +79,8 @@ pub fn oom() -> ! {
 
 /// Set the OOM handler.
 ///
-/// This is called when the process is out-of-memory.
+/// This is called when the process is out-of-memory. With the `oom_unwind` feature, the handler
+/// may unwind to recover from the condition (see [`oom`](fn.oom.html)).
 #[inline]
 pub fn set_oom_handler(handler: fn() -> !) {
     OOM_HANDLER.store(handler as *mut (), atomic::Ordering::SeqCst);
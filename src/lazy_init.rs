@@ -53,6 +53,18 @@ impl<F: FnMut() -> T, T> LazyInit<F, T> {
         }
     }
 
+    /// Get a mutable reference to the inner value, if it is initialized.
+    ///
+    /// In contrast to [`get`](#method.get), this never runs the initializer.
+    #[inline]
+    #[cfg(feature = "oom_unwind")]
+    pub fn get_initialized(&mut self) -> Option<&mut T> {
+        match self.state {
+            State::Initialized(ref mut x) => Some(x),
+            State::Uninitialized(_) => None,
+        }
+    }
+
     /// Get the inner of the container.
     ///
     /// This won't mutate the container itself, since it consumes it. The initializer will (if
@@ -36,23 +36,66 @@ mod log;
 mod tls;
 #[cfg(feature = "allocator")]
 mod symbols;
+#[cfg(feature = "stats")]
+mod stats;
+#[cfg(feature = "debug_tools")]
+mod trace;
+#[cfg(feature = "debug_tools")]
+mod snapshot;
+#[cfg(feature = "syscall_timing")]
+mod timing;
+#[cfg(feature = "profiling")]
+mod profile;
+#[cfg(feature = "sanitizer")]
+mod sanitizer;
+#[cfg(feature = "quarantine")]
+mod quarantine;
 
 mod allocator;
+mod arena;
 mod block;
 mod bookkeeper;
 mod brk;
+mod bump;
 mod cell;
+mod event;
 mod fail;
 mod lazy_init;
 mod leak;
 mod prelude;
 mod ptr;
+// Only used by the debug tools so far.
+#[allow(dead_code)]
+mod raw_box;
 mod sync;
 mod sys;
 mod vec;
 
-pub use allocator::{alloc, free, realloc, realloc_inplace};
+pub use allocator::{alloc, alloc_cache_aligned, alloc_contiguous, free, realloc, realloc_inplace,
+                    realloc_tracked, drain_to_os, max_alloc_size, owns, add_region};
+pub use arena::Arena;
+pub use bump::{begin_bump, end_bump};
+pub use event::{set_event_callback, AllocEvent};
+pub use block::{alignment_overhead, NUM_CLASSES};
+pub use bookkeeper::{set_free_scribble, set_search_bound, set_zero_on_alloc};
 pub use fail::set_oom_handler;
-pub use sys::sbrk;
+pub use sys::{heap_size, sbrk, set_heap_limit};
+#[cfg(feature = "stats")]
+pub use stats::{stats, live_bytes_by_tag, set_high_water_callback, Stats};
+#[cfg(feature = "stats")]
+pub use allocator::{alloc_tagged, free_tagged, realloc_tagged, overhead_bytes};
+#[cfg(feature = "debug_tools")]
+pub use trace::dump_recent_events;
+#[cfg(feature = "debug_tools")]
+pub use snapshot::{snapshot_free_list, diff_snapshots, FreeListSnapshot, FreeListDiff,
+                   FreeListChange};
+#[cfg(feature = "syscall_timing")]
+pub use timing::dump_syscall_timings;
+#[cfg(feature = "profiling")]
+pub use profile::write_profile_snapshot;
+#[cfg(feature = "debug_tools")]
+pub use allocator::{free_blocks, fragmentation, free_counts, FreeBlockIter, FragmentationReport};
 #[cfg(feature = "tls")]
 pub use fail::set_thread_oom_handler;
+#[cfg(feature = "tls")]
+pub use allocator::set_thread_cache_limit;
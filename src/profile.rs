@@ -0,0 +1,87 @@
+//! Heap profiling.
+//!
+//! This writes snapshots of the heap in a simple line protocol, which can be collected over time
+//! and fed to external tools for visualization.
+
+use core::fmt;
+use core::sync::atomic::{self, AtomicUsize};
+
+use {allocator, stats};
+
+/// The number of snapshots taken so far.
+///
+/// ralloc has no clock, so this numbers the snapshots in place of a timestamp.
+static SNAPSHOTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Write a snapshot of the heap.
+///
+/// The snapshot is a single line of space-separated `key=value` fields:
+///
+/// - `snapshot`: the sequence number of the snapshot.
+/// - `live`: the number of live bytes.
+/// - `peak`: the highest number of live bytes seen so far.
+/// - `free`: the number of free bytes in the global allocator.
+/// - `classes`: the number of free blocks of the global allocator in every size class, separated
+///   by commas (see [`free_counts`](fn.free_counts.html)).
+///
+/// For example:
+///
+/// ```notrust
+/// snapshot=3 live=4096 peak=8192 free=1024 classes=2,0,1,0,0,0,0,0,0,0,0,0,0,0,0,0
+/// ```
+///
+/// The free blocks are read under the lock (see [`fragmentation`](fn.fragmentation.html) and
+/// `free_counts`), which is released before anything is written to `out`, so `out` is allowed to
+/// allocate. Nothing is allocated otherwise. The free bytes and the classes are read one after
+/// another, so they might disagree slightly, if other threads free memory in the meantime. Memory
+/// held by the thread-local allocators is not included.
+pub fn write_profile_snapshot<W: fmt::Write>(out: &mut W) -> fmt::Result {
+    let free = allocator::fragmentation().total_free;
+    let classes = allocator::free_counts();
+
+    write!(out, "snapshot={} live={} peak={} free={} classes=",
+           SNAPSHOTS.fetch_add(1, atomic::Ordering::Relaxed), stats::live_bytes(),
+           stats::peak_bytes(), free)?;
+    for (n, count) in classes.iter().enumerate() {
+        if n != 0 {
+            out.write_char(',')?;
+        }
+        write!(out, "{}", count)?;
+    }
+
+    out.write_char('\n')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use block;
+    use write::Buffer;
+
+    #[test]
+    fn test_snapshot() {
+        let mut buffer = Buffer::new();
+        write_profile_snapshot(&mut buffer).unwrap();
+        let snapshot = buffer.as_str();
+
+        assert!(snapshot.ends_with('\n'));
+
+        let mut fields = snapshot.trim_right().split(' ');
+        for key in &["snapshot", "live", "peak", "free"] {
+            let mut field = fields.next().unwrap().split('=');
+            assert_eq!(field.next(), Some(*key));
+            field.next().unwrap().parse::<usize>().unwrap();
+        }
+
+        let mut field = fields.next().unwrap().split('=');
+        assert_eq!(field.next(), Some("classes"));
+        let classes = field.next().unwrap().split(',');
+        assert_eq!(classes.clone().count(), block::NUM_CLASSES);
+        for count in classes {
+            count.parse::<usize>().unwrap();
+        }
+
+        assert!(fields.next().is_none());
+    }
+}
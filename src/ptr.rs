@@ -1,7 +1,7 @@
 //! Pointer wrappers.
 
 use core::nonzero::NonZero;
-use core::{ops, marker};
+use core::{ops, marker, mem, isize};
 
 /// A pointer wrapper type.
 ///
@@ -68,6 +68,26 @@ impl<T> Pointer<T> {
     pub unsafe fn offset(self, diff: isize) -> Pointer<T> {
         Pointer::new(self.ptr.offset(diff))
     }
+
+    /// Offset this pointer by `count` elements, if possible.
+    ///
+    /// This is like [`offset`](#method.offset), but returns `None` instead of wrapping, if the
+    /// offset in bytes does not fit in `isize`, or the result would wrap around the address space.
+    ///
+    /// # Safety
+    ///
+    /// This is unsafe, due to OOB offsets being undefined behavior.
+    #[inline]
+    #[allow(cast_possible_wrap)]
+    pub unsafe fn offset_checked(self, count: usize) -> Option<Pointer<T>> {
+        match count.checked_mul(mem::size_of::<T>()) {
+            Some(bytes) if bytes <= isize::MAX as usize
+                && (*self.ptr as usize).checked_add(bytes).is_some() => {
+                Some(Pointer::new(self.ptr.offset(count as isize)))
+            },
+            _ => None,
+        }
+    }
 }
 
 impl<T> Default for Pointer<T> {
@@ -112,6 +132,22 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_offset_checked() {
+        let mut x = [1u16, 2];
+
+        unsafe {
+            let ptr = Pointer::new(&mut x[0] as *mut u16);
+            assert_eq!(**ptr.clone().offset_checked(1).unwrap(), 2);
+
+            // The byte offset overflows `isize`.
+            assert!(ptr.clone().offset_checked(isize::MAX as usize / 2 + 1).is_none());
+            // The offset wraps around the address space.
+            let high = Pointer::new(!0 as *mut u8);
+            assert!(high.offset_checked(1).is_none());
+        }
+    }
+
     #[test]
     fn test_empty() {
         assert_eq!(*Pointer::<u8>::empty() as usize, 1);
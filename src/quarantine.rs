@@ -0,0 +1,100 @@
+//! Quarantine of freed blocks.
+//!
+//! Freed blocks are held back for a while before they can be reused, which widens the window in
+//! which use-after-free bugs hit freed (and, possibly, poisoned) memory, rather than a new
+//! allocation.
+
+use prelude::*;
+
+/// The maximal number of bytes in quarantine.
+///
+/// Blocks larger than this bypass the quarantine.
+const BUDGET: usize = 64 * 1024;
+/// The maximal number of blocks in quarantine.
+const SLOTS: usize = 64;
+
+/// A FIFO of quarantined blocks.
+struct Quarantine {
+    /// The quarantined blocks as `(address, size)` pairs, in the order they were freed.
+    ///
+    /// This is a ring buffer starting at `head`.
+    blocks: [(usize, usize); SLOTS],
+    /// The index of the oldest block.
+    head: usize,
+    /// The number of quarantined blocks.
+    len: usize,
+    /// The total size of the quarantined blocks.
+    bytes: usize,
+}
+
+impl Quarantine {
+    /// Take the oldest block out of quarantine.
+    ///
+    /// The quarantine must not be empty.
+    fn pop(&mut self) -> Block {
+        debug_assert!(self.len != 0, "Popping from an empty quarantine.");
+
+        let (ptr, size) = self.blocks[self.head];
+        self.head = (self.head + 1) % SLOTS;
+        self.len -= 1;
+        self.bytes -= size;
+
+        unsafe { Block::from_raw_parts(Pointer::new(ptr as *mut u8), size) }
+    }
+
+    /// Put a block in quarantine.
+    ///
+    /// There must be a free slot.
+    fn push(&mut self, block: Block) {
+        debug_assert!(self.len != SLOTS, "Pushing to a full quarantine.");
+
+        self.bytes += block.size();
+        self.blocks[(self.head + self.len) % SLOTS] =
+            (*Pointer::from(block.empty_left()) as usize, block.size());
+        self.len += 1;
+    }
+}
+
+/// The quarantine.
+///
+/// This is shared between all the allocators, so the blocks are released to whichever allocator
+/// frees the block pushing them out.
+static QUARANTINE: Mutex<Quarantine> = Mutex::new(Quarantine {
+    blocks: [(0, 0); SLOTS],
+    head: 0,
+    len: 0,
+    bytes: 0,
+});
+
+/// Put a freed block in quarantine.
+///
+/// The oldest blocks are released through `release`, until the quarantine is within its budget
+/// again. Blocks exceeding the budget on their own, as well as empty blocks, are released right
+/// away.
+///
+/// `release` is called with the quarantine locked, so it must not reenter it.
+pub fn delay<F: FnMut(Block)>(block: Block, mut release: F) {
+    if block.is_empty() || block.size() > BUDGET {
+        release(block);
+        return;
+    }
+
+    let mut quarantine = QUARANTINE.lock();
+
+    while quarantine.len == SLOTS || quarantine.bytes + block.size() > BUDGET {
+        release(quarantine.pop());
+    }
+
+    quarantine.push(block);
+}
+
+/// Release every block in quarantine through `release`, oldest first.
+///
+/// `release` is called with the quarantine locked, so it must not reenter it.
+pub fn flush<F: FnMut(Block)>(mut release: F) {
+    let mut quarantine = QUARANTINE.lock();
+
+    while quarantine.len != 0 {
+        release(quarantine.pop());
+    }
+}
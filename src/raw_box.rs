@@ -0,0 +1,118 @@
+//! A box for the allocator's own data.
+//!
+//! This takes memory directly from the program break, keeping it apart from the memory handed out
+//! to users.
+
+use prelude::*;
+
+use core::{mem, ops, ptr};
+
+use {block, sys};
+
+/// A box, which stores its value directly on the program break.
+///
+/// This bypasses the allocators (and thus their pools and caches) entirely, which makes it safe
+/// to use for the allocator's own metadata: constructing or dropping it never reenters the
+/// allocators.
+///
+/// # Leaks
+///
+/// When dropped, the memory is only given back to the OS if the box is still at the top of the
+/// program break, i.e. nothing above it has been allocated from the program break since. Otherwise,
+/// the memory is leaked for good rather than entering the pools (it still counts toward the heap
+/// limit), so this is meant for few, long-lived values.
+pub struct RawBox<T> {
+    /// The block taken from the program break, including the alignment padding.
+    block: Block,
+    /// The pointer to the value.
+    ptr: Pointer<T>,
+}
+
+impl<T> RawBox<T> {
+    /// Move a value into a new box.
+    ///
+    /// # Errors
+    ///
+    /// The OOM handler handles out-of-memory conditions.
+    pub fn new(value: T) -> RawBox<T> {
+        // Overallocate, such that there is room for the value after the alignment padding.
+        let block = Block::brk(mem::size_of::<T>() + mem::align_of::<T>());
+        let (ptr, _) = block::align_pointer(*Pointer::from(block.empty_left()),
+                                            mem::align_of::<T>());
+
+        unsafe {
+            ptr::write(ptr as *mut T, value);
+
+            RawBox {
+                block: block,
+                ptr: Pointer::new(ptr as *mut T),
+            }
+        }
+    }
+}
+
+impl<T> ops::Deref for RawBox<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        unsafe { &**self.ptr }
+    }
+}
+
+impl<T> ops::DerefMut for RawBox<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut **self.ptr }
+    }
+}
+
+impl<T> Drop for RawBox<T> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(*self.ptr);
+
+            // Give the memory back, if it is still at the top of the program break.
+            let _ = sys::shrink_brk(*Pointer::from(self.block.empty_right()), self.block.size());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use core::mem;
+    use core::sync::atomic::{self, AtomicUsize};
+
+    #[test]
+    fn test_raw_box() {
+        let mut b = RawBox::new([0usize; 16]);
+        b[3] = 42;
+        b[15] = 7;
+
+        assert_eq!(b[3], 42);
+        assert_eq!(b[15], 7);
+        assert_eq!(b.iter().fold(0, |acc, x| acc + x), 49);
+        assert_eq!(&*b as *const _ as usize % mem::align_of::<usize>(), 0);
+    }
+
+    #[test]
+    fn test_raw_box_drop() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct Counter;
+
+        impl Drop for Counter {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, atomic::Ordering::SeqCst);
+            }
+        }
+
+        let b = RawBox::new(Counter);
+        assert_eq!(DROPS.load(atomic::Ordering::SeqCst), 0);
+
+        drop(b);
+        assert_eq!(DROPS.load(atomic::Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,178 @@
+//! AddressSanitizer hooks.
+//!
+//! With the `sanitizer` feature, buffers are unpoisoned when allocated and poisoned when freed,
+//! so AddressSanitizer can detect use-after-free and overflows of ralloc's buffers. The red
+//! zones (see the `redzone` feature) are kept poisoned for the lifetime of the buffer, catching
+//! overflows. The program must be linked with the AddressSanitizer runtime.
+//!
+//! ralloc touches free memory itself (e.g. when zeroing, scribbling or moving buffers, and when
+//! growing its pools), so the poisoning is maintained by the bookkeeper as well: every block in
+//! a pool is kept poisoned, and is unpoisoned when it leaves the pool, before ralloc writes to
+//! it. Blocks held in quarantine (see the `quarantine` feature) stay poisoned too.
+
+use prelude::*;
+
+#[cfg(not(test))]
+extern {
+    /// Mark a memory region as unaddressable.
+    fn __asan_poison_memory_region(addr: *const u8, size: usize);
+    /// Mark a memory region as addressable.
+    fn __asan_unpoison_memory_region(addr: *const u8, size: usize);
+}
+
+/// Mark a buffer of `size` bytes, followed by a red zone of `redzone` bytes, as allocated.
+#[inline]
+#[allow(cast_possible_wrap)]
+pub fn allocated(ptr: *const u8, size: usize, redzone: usize) {
+    unsafe {
+        __asan_unpoison_memory_region(ptr, size);
+        if redzone != 0 {
+            __asan_poison_memory_region(ptr.offset(size as isize), redzone);
+        }
+    }
+}
+
+/// Mark a region of `size` bytes (including the red zone) as freed.
+#[inline]
+pub fn freed(ptr: *const u8, size: usize) {
+    unsafe {
+        __asan_poison_memory_region(ptr, size);
+    }
+}
+
+/// The maximal number of poisoned regions tracked in place of the runtime.
+///
+/// When the table is full, further regions are not tracked, which only misses detections.
+#[cfg(test)]
+const MAX_REGIONS: usize = 1024;
+
+/// The poisoned regions, as `(start, end)` pairs, used for testing in place of the runtime.
+///
+/// `(0, 0)` marks an unused entry.
+#[cfg(test)]
+static POISONED: Mutex<[(usize, usize); MAX_REGIONS]> = Mutex::new([(0, 0); MAX_REGIONS]);
+
+/// Remove a region from the poisoned regions (test replacement of the runtime).
+#[cfg(test)]
+fn cut(regions: &mut [(usize, usize); MAX_REGIONS], start: usize, end: usize) {
+    for i in 0..MAX_REGIONS {
+        let (a, b) = regions[i];
+
+        if a < end && start < b {
+            regions[i] = (0, 0);
+
+            if a < start {
+                track(regions, a, start);
+            }
+            if end < b {
+                track(regions, end, b);
+            }
+        }
+    }
+}
+
+/// Add a region to the poisoned regions, if there is room (test replacement of the runtime).
+#[cfg(test)]
+fn track(regions: &mut [(usize, usize); MAX_REGIONS], start: usize, end: usize) {
+    if let Some(entry) = regions.iter_mut().find(|x| x.0 == x.1) {
+        *entry = (start, end);
+    }
+}
+
+/// Record a poisoned region (test replacement of the runtime).
+#[cfg(test)]
+unsafe fn __asan_poison_memory_region(addr: *const u8, size: usize) {
+    let start = addr as usize;
+    let mut regions = POISONED.lock();
+
+    cut(&mut regions, start, start + size);
+    if size != 0 {
+        track(&mut regions, start, start + size);
+    }
+}
+
+/// Record an unpoisoned region (test replacement of the runtime).
+#[cfg(test)]
+unsafe fn __asan_unpoison_memory_region(addr: *const u8, size: usize) {
+    let start = addr as usize;
+
+    cut(&mut POISONED.lock(), start, start + size);
+}
+
+/// Is any byte of a region poisoned?
+#[cfg(test)]
+pub fn is_poisoned(ptr: *const u8, size: usize) -> bool {
+    let start = ptr as usize;
+
+    POISONED.lock().iter().any(|&(a, b)| a < start + size && start < b)
+}
+
+/// Assert that ralloc may access a region, as the runtime would when ralloc is instrumented.
+///
+/// # Panics
+///
+/// This will panic if any byte of the region is poisoned.
+#[cfg(test)]
+pub fn assert_unpoisoned(ptr: *const u8, size: usize) {
+    assert!(!is_poisoned(ptr, size), "Access to the poisoned region 0x{:x}[{}].", ptr as usize,
+            size);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use allocator;
+
+    #[test]
+    fn test_hooks() {
+        let ptr = allocator::alloc(16, 1);
+        assert!(!is_poisoned(ptr, 16));
+
+        unsafe {
+            allocator::free(ptr, 16);
+            assert!(is_poisoned(ptr, 16));
+        }
+    }
+
+    #[test]
+    fn test_poison_cut() {
+        let buf = [0u8; 64];
+        let ptr = buf.as_ptr();
+
+        unsafe {
+            freed(ptr, 64);
+            allocated(ptr.offset(16), 16, 8);
+
+            assert!(is_poisoned(ptr, 16));
+            assert!(!is_poisoned(ptr.offset(16), 16));
+            assert!(is_poisoned(ptr.offset(32), 8));
+            assert!(is_poisoned(ptr.offset(40), 24));
+
+            allocated(ptr, 64, 0);
+            assert!(!is_poisoned(ptr, 64));
+        }
+    }
+
+    #[test]
+    fn test_internal_accesses() {
+        // Move buffers around, so ralloc copies, splits and merges free memory, and grows the
+        // pools. Every access of ralloc itself is checked not to touch poisoned memory.
+        let mut ptrs = [0 as *mut u8; 64];
+
+        unsafe {
+            for (n, i) in ptrs.iter_mut().enumerate() {
+                *i = allocator::alloc(n + 1, 8);
+                *i = allocator::realloc(*i, n + 1, 2 * n + 100, 8);
+            }
+            for (n, i) in ptrs.iter().enumerate().filter(|&(n, _)| n % 2 == 0) {
+                allocator::free(*i, 2 * n + 100);
+            }
+            for (n, i) in ptrs.iter_mut().enumerate().filter(|&(n, _)| n % 2 == 1) {
+                *i = allocator::realloc(*i, 2 * n + 100, 50, 8);
+                assert!(!is_poisoned(*i, 50));
+                allocator::free(*i, 50);
+            }
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Free list snapshots.
+//!
+//! This captures the free blocks of the global allocator, such that the effect of a sequence of
+//! operations can be compared against a reference model (e.g. when fuzzing).
+
+use core::{cmp, slice};
+
+use allocator;
+use raw_box::RawBox;
+
+/// The maximal number of blocks in a snapshot.
+const CAPACITY: usize = 1024;
+
+/// A snapshot of the free blocks of the global allocator.
+///
+/// The blocks are stored as `(address, size)` pairs, ordered by address. The snapshot is stored
+/// on the program break (see `RawBox`), so taking it does not modify the free list.
+///
+/// # Leaks
+///
+/// Every snapshot takes 16 KiB (on 64-bit targets) from the program break. When dropped, this is
+/// only given back if nothing else was allocated from the program break since, and it is leaked
+/// otherwise, counting toward the heap limit (see `set_heap_limit`) for good. Snapshots are thus
+/// meant for tests and debugging, taken a bounded number of times.
+pub struct FreeListSnapshot {
+    /// The blocks.
+    blocks: RawBox<[(usize, usize); CAPACITY]>,
+    /// The number of blocks.
+    len: usize,
+    /// Were all the blocks captured?
+    complete: bool,
+}
+
+impl FreeListSnapshot {
+    /// Capture some free blocks, given as `(address, size)` pairs ordered by address.
+    fn from_blocks<I: Iterator<Item = (usize, usize)>>(blocks: I) -> FreeListSnapshot {
+        let mut res = FreeListSnapshot {
+            blocks: RawBox::new([(0, 0); CAPACITY]),
+            len: 0,
+            complete: true,
+        };
+
+        for block in blocks {
+            if res.len == CAPACITY {
+                res.complete = false;
+                break;
+            }
+
+            res.blocks[res.len] = block;
+            res.len += 1;
+        }
+
+        res
+    }
+
+    /// Get the blocks as `(address, size)` pairs, ordered by address.
+    #[inline]
+    pub fn blocks(&self) -> &[(usize, usize)] {
+        &self.blocks[..self.len]
+    }
+
+    /// Were all the free blocks captured?
+    ///
+    /// At most 1024 blocks are captured. If there were more, the snapshot only holds the lowest
+    /// ones.
+    #[inline]
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+}
+
+/// Take a snapshot of the free list of the global allocator.
+///
+/// The free blocks are read under the lock, so the snapshot is consistent. Memory held by the
+/// thread-local allocators is not included.
+pub fn snapshot_free_list() -> FreeListSnapshot {
+    FreeListSnapshot::from_blocks(allocator::free_blocks())
+}
+
+/// A change between two free list snapshots.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FreeListChange {
+    /// A block at some address was added, with some size.
+    Added(usize, usize),
+    /// A block at some address was removed, with some size.
+    Removed(usize, usize),
+    /// The block at some address was resized, from the first size to the second.
+    Resized(usize, usize, usize),
+}
+
+/// The changes between two free list snapshots.
+///
+/// This iterates over the changes, ordered by address.
+pub struct FreeListDiff<'a> {
+    /// The remaining blocks of the older snapshot.
+    old: slice::Iter<'a, (usize, usize)>,
+    /// The remaining blocks of the newer snapshot.
+    new: slice::Iter<'a, (usize, usize)>,
+}
+
+impl<'a> Iterator for FreeListDiff<'a> {
+    type Item = FreeListChange;
+
+    fn next(&mut self) -> Option<FreeListChange> {
+        loop {
+            // Both lists are ordered by address, so we walk them in lockstep.
+            let res = match (self.old.as_slice().first(), self.new.as_slice().first()) {
+                (None, None) => return None,
+                (Some(&(addr, size)), None) => {
+                    self.old.next();
+                    FreeListChange::Removed(addr, size)
+                },
+                (None, Some(&(addr, size))) => {
+                    self.new.next();
+                    FreeListChange::Added(addr, size)
+                },
+                (Some(&(old_addr, old_size)), Some(&(new_addr, new_size))) => {
+                    match old_addr.cmp(&new_addr) {
+                        cmp::Ordering::Less => {
+                            self.old.next();
+                            FreeListChange::Removed(old_addr, old_size)
+                        },
+                        cmp::Ordering::Greater => {
+                            self.new.next();
+                            FreeListChange::Added(new_addr, new_size)
+                        },
+                        cmp::Ordering::Equal => {
+                            self.old.next();
+                            self.new.next();
+
+                            if old_size == new_size {
+                                // Unchanged.
+                                continue;
+                            }
+
+                            FreeListChange::Resized(old_addr, old_size, new_size)
+                        },
+                    }
+                },
+            };
+
+            return Some(res);
+        }
+    }
+}
+
+/// Compare two free list snapshots.
+///
+/// The changes turning `old` into `new` are iterated over, without allocating.
+pub fn diff_snapshots<'a>(old: &'a FreeListSnapshot, new: &'a FreeListSnapshot)
+                          -> FreeListDiff<'a> {
+    FreeListDiff {
+        old: old.blocks().iter(),
+        new: new.blocks().iter(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let old = FreeListSnapshot::from_blocks([(0x1000, 64), (0x2000, 32), (0x3000, 16)]
+                                                    .iter().cloned());
+        // Allocate 16 bytes from the start of the first block, and grow the last one.
+        let new = FreeListSnapshot::from_blocks([(0x1010, 48), (0x2000, 32), (0x3000, 48)]
+                                                    .iter().cloned());
+
+        let mut diff = diff_snapshots(&old, &new);
+        assert_eq!(diff.next(), Some(FreeListChange::Removed(0x1000, 64)));
+        assert_eq!(diff.next(), Some(FreeListChange::Added(0x1010, 48)));
+        assert_eq!(diff.next(), Some(FreeListChange::Resized(0x3000, 16, 48)));
+        assert_eq!(diff.next(), None);
+
+        // A snapshot does not differ from itself.
+        assert_eq!(diff_snapshots(&new, &new).next(), None);
+    }
+
+    #[test]
+    fn test_snapshot_free_list() {
+        let snapshot = snapshot_free_list();
+
+        // The blocks are ordered by address, and do not overlap.
+        for pair in snapshot.blocks().windows(2) {
+            assert!(pair[0].0 + pair[0].1 <= pair[1].0);
+        }
+    }
+}
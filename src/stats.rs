@@ -0,0 +1,298 @@
+//! Allocator statistics.
+//!
+//! This keeps a number of counters, which can be used to diagnose the behavior of the allocator.
+//! The counters are global and shared between all the allocators.
+
+use prelude::*;
+
+use core::sync::atomic::{self, AtomicUsize, AtomicBool};
+
+/// The memory ordering of the counters.
+///
+/// The counters are not used for synchronization, so relaxed ordering is sufficient. The
+/// `strict_stats` feature makes them sequentially consistent, which is handy when debugging the
+/// counters themselves.
+#[cfg(not(feature = "strict_stats"))]
+const ORDERING: atomic::Ordering = atomic::Ordering::Relaxed;
+/// The memory ordering of the counters.
+#[cfg(feature = "strict_stats")]
+const ORDERING: atomic::Ordering = atomic::Ordering::SeqCst;
+
+/// The number of block splits.
+static SPLITS: AtomicUsize = AtomicUsize::new(0);
+/// The number of block merges.
+static MERGES: AtomicUsize = AtomicUsize::new(0);
+
+/// The maximal number of distinct tags (besides the untagged default) with live allocations.
+///
+/// Allocations with tags beyond this are not accounted.
+const MAX_TAGS: usize = 32;
+/// The live bytes of untagged allocations (i.e. tag 0).
+static UNTAGGED_BYTES: AtomicUsize = AtomicUsize::new(0);
+/// The live bytes of tagged allocations.
+///
+/// Every entry is a tag and the number of live bytes with it. Entries without any live bytes are
+/// unused.
+static TAGGED_BYTES: Mutex<[(u32, usize); MAX_TAGS]> = Mutex::new([(0, 0); MAX_TAGS]);
+/// The live bytes of all allocations, regardless of their tag.
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+/// The number of live allocations.
+static LIVE_ALLOCS: AtomicUsize = AtomicUsize::new(0);
+/// The highest number of live bytes seen.
+static PEAK_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of live bytes, which triggers the high-water callback.
+///
+/// `!0` means that no threshold is set.
+static HIGH_WATER_THRESHOLD: AtomicUsize = AtomicUsize::new(!0);
+/// Is the high-water callback armed?
+///
+/// This is cleared when the callback fires, and set again when the live bytes drop below the
+/// threshold.
+static HIGH_WATER_ARMED: AtomicBool = AtomicBool::new(false);
+/// The high-water callback.
+static HIGH_WATER_CALLBACK: Mutex<Option<fn(usize)>> = Mutex::new(None);
+
+/// A snapshot of the allocator statistics.
+#[derive(Clone, Copy, Debug)]
+pub struct Stats {
+    /// The number of times a block has been split.
+    ///
+    /// This includes splitting at the aligner.
+    pub splits: usize,
+    /// The number of times a block has been merged with its right neighbor.
+    ///
+    /// A high split to merge ratio is an indicator of fragmentation.
+    pub merges: usize,
+}
+
+/// Subtract `n` from a counter, stopping at 0.
+///
+/// Buffers may be freed partially, or may not come from ralloc at all, so a counter of live memory
+/// cannot rely on frees matching allocations. This returns the new value.
+fn sub_saturating(counter: &AtomicUsize, n: usize) -> usize {
+    let mut old = counter.load(ORDERING);
+    loop {
+        let new = old.saturating_sub(n);
+        let res = counter.compare_and_swap(old, new, ORDERING);
+        if res == old {
+            return new;
+        }
+        old = res;
+    }
+}
+
+/// Record that a block has been split.
+#[inline]
+pub fn record_split() {
+    SPLITS.fetch_add(1, ORDERING);
+}
+
+/// Record that two blocks have been merged.
+#[inline]
+pub fn record_merge() {
+    MERGES.fetch_add(1, ORDERING);
+}
+
+/// Record that `size` bytes have been allocated with some tag.
+///
+/// This might call the high-water callback, so it must not be called with an allocator locked.
+pub fn record_alloc(tag: u32, size: usize) {
+    LIVE_ALLOCS.fetch_add(1, ORDERING);
+    let live = LIVE_BYTES.fetch_add(size, ORDERING) + size;
+
+    // Update the peak. Racing updates retry until they don't make it worse.
+    let mut peak = PEAK_BYTES.load(ORDERING);
+    while live > peak {
+        let old = PEAK_BYTES.compare_and_swap(peak, live, ORDERING);
+        if old == peak {
+            break;
+        }
+        peak = old;
+    }
+
+    if live >= HIGH_WATER_THRESHOLD.load(ORDERING) && HIGH_WATER_ARMED.swap(false, ORDERING) {
+        // Copy the callback out, so the lock is not held while it runs.
+        let callback = *HIGH_WATER_CALLBACK.lock();
+        if let Some(callback) = callback {
+            callback(live);
+        }
+    }
+
+    if tag == 0 {
+        UNTAGGED_BYTES.fetch_add(size, ORDERING);
+        return;
+    }
+
+    let mut tags = TAGGED_BYTES.lock();
+
+    // Find the entry of the tag.
+    if let Some(entry) = tags.iter_mut().find(|&&mut (t, bytes)| t == tag && bytes != 0) {
+        entry.1 += size;
+        return;
+    }
+
+    // The tag has no live allocations, so we take an unused entry.
+    if let Some(entry) = tags.iter_mut().find(|&&mut (_, bytes)| bytes == 0) {
+        *entry = (tag, size);
+    }
+}
+
+/// Record that `size` bytes have been freed with some tag.
+pub fn record_free(tag: u32, size: usize) {
+    // A buffer freed in several parts counts as several frees, so this saturates as well.
+    sub_saturating(&LIVE_ALLOCS, 1);
+
+    // Rearm the high-water callback, once we are back below the threshold. Partial frees might
+    // free more than was recorded, so the count saturates, rather than wrapping above it.
+    let live = sub_saturating(&LIVE_BYTES, size);
+    if live < HIGH_WATER_THRESHOLD.load(ORDERING) {
+        HIGH_WATER_ARMED.store(true, ORDERING);
+    }
+
+    if tag == 0 {
+        sub_saturating(&UNTAGGED_BYTES, size);
+        return;
+    }
+
+    if let Some(entry) = TAGGED_BYTES.lock().iter_mut().find(|&&mut (t, bytes)| t == tag && bytes != 0) {
+        entry.1 = entry.1.saturating_sub(size);
+    }
+}
+
+/// Get the number of live bytes allocated with some tag.
+///
+/// Tag 0 represents the allocations done without a tag (i.e. through `alloc`).
+pub fn live_bytes_by_tag(tag: u32) -> usize {
+    if tag == 0 {
+        UNTAGGED_BYTES.load(ORDERING)
+    } else {
+        TAGGED_BYTES.lock()
+            .iter()
+            .find(|&&(t, bytes)| t == tag && bytes != 0)
+            .map_or(0, |&(_, bytes)| bytes)
+    }
+}
+
+/// Get the number of live bytes, regardless of their tag.
+#[inline]
+pub fn live_bytes() -> usize {
+    LIVE_BYTES.load(ORDERING)
+}
+
+/// Get the number of live allocations.
+///
+/// This assumes that every buffer is freed whole. Otherwise, it undercounts.
+#[inline]
+pub fn live_allocs() -> usize {
+    LIVE_ALLOCS.load(ORDERING)
+}
+
+/// Get the highest number of live bytes seen so far.
+#[inline]
+pub fn peak_bytes() -> usize {
+    PEAK_BYTES.load(ORDERING)
+}
+
+/// Set a callback, which is called when the live bytes reach `threshold_bytes`.
+///
+/// The callback is called with the number of live bytes, the first time they reach the threshold
+/// from below. It is not called again, until the live bytes have dropped below the threshold.
+/// This gives the program a chance to shed load before running out of memory.
+///
+/// The callback is called with no allocator locked, so it is allowed to allocate. If the live
+/// bytes are already at the threshold, the callback is called on the next crossing.
+pub fn set_high_water_callback(threshold_bytes: usize, callback: fn(usize)) {
+    *HIGH_WATER_CALLBACK.lock() = Some(callback);
+    HIGH_WATER_THRESHOLD.store(threshold_bytes, ORDERING);
+    HIGH_WATER_ARMED.store(LIVE_BYTES.load(ORDERING) < threshold_bytes, ORDERING);
+}
+
+/// Get a snapshot of the allocator statistics.
+///
+/// Note that the counters are read independently, so the snapshot might be slightly inconsistent
+/// when other threads are using the allocator.
+pub fn stats() -> Stats {
+    Stats {
+        splits: SPLITS.load(ORDERING),
+        merges: MERGES.load(ORDERING),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use prelude::*;
+
+    use super::*;
+    use allocator;
+
+    use core::sync::atomic::{self, AtomicUsize};
+
+    #[test]
+    fn test_split_merge() {
+        let mut arr = [0u8; 16];
+        let block = unsafe {
+            Block::from_raw_parts(Pointer::new(&mut arr[0] as *mut u8), 16)
+        };
+
+        let before = stats();
+
+        let (mut a, b) = block.split(4);
+        let (mut b, mut c) = b.split(4);
+        a.merge_right(&mut b).unwrap();
+        a.merge_right(&mut c).unwrap();
+
+        let after = stats();
+
+        // Other threads might use the allocator in the meantime, so we can only bound the counters.
+        assert!(after.splits - before.splits >= 2);
+        assert!(after.merges - before.merges >= 2);
+    }
+
+    #[test]
+    fn test_tags() {
+        let a = allocator::alloc_tagged(100, 1, 0xCA7);
+        let b = allocator::alloc_tagged(200, 8, 0xCA7);
+        let c = allocator::alloc_tagged(50, 1, 0xD06);
+
+        assert_eq!(live_bytes_by_tag(0xCA7), 300);
+        assert_eq!(live_bytes_by_tag(0xD06), 50);
+
+        unsafe {
+            allocator::free_tagged(a, 100, 0xCA7);
+        }
+
+        assert_eq!(live_bytes_by_tag(0xCA7), 200);
+        assert_eq!(live_bytes_by_tag(0xD06), 50);
+
+        unsafe {
+            allocator::free_tagged(b, 200, 0xCA7);
+            allocator::free_tagged(c, 50, 0xD06);
+        }
+
+        assert_eq!(live_bytes_by_tag(0xCA7), 0);
+        assert_eq!(live_bytes_by_tag(0xD06), 0);
+    }
+
+    #[test]
+    fn test_tagged_realloc() {
+        unsafe {
+            let a = allocator::alloc_tagged(100, 1, 0xB0A);
+            let a = allocator::realloc_tagged(a, 100, 300, 1, 0xB0A);
+            assert_eq!(live_bytes_by_tag(0xB0A), 300);
+
+            allocator::free_tagged(a, 300, 0xB0A);
+            assert_eq!(live_bytes_by_tag(0xB0A), 0);
+        }
+    }
+
+    #[test]
+    fn test_sub_saturating() {
+        let counter = AtomicUsize::new(5);
+
+        assert_eq!(sub_saturating(&counter, 3), 2);
+        // Freeing more than is live stops at 0, rather than wrapping.
+        assert_eq!(sub_saturating(&counter, 8), 0);
+        assert_eq!(counter.load(atomic::Ordering::SeqCst), 0);
+    }
+}
@@ -49,6 +49,19 @@ impl<T> Mutex<T> {
             mutex: self,
         }
     }
+
+    /// Release the lock without a guard.
+    ///
+    /// # Safety
+    ///
+    /// This must only be called when the lock is held by the current thread. The guard holding it
+    /// must not be used or dropped afterwards, since the lock might be held by someone else by
+    /// then.
+    #[inline]
+    #[cfg(feature = "oom_unwind")]
+    pub unsafe fn force_unlock(&self) {
+        self.locked.store(false, atomic::Ordering::SeqCst);
+    }
 }
 
 /// A mutex guard.
@@ -5,12 +5,72 @@ extern crate ralloc_shim as shim;
 use prelude::*;
 
 use core::mem;
+use core::sync::atomic::{self, AtomicUsize};
+
+#[cfg(feature = "syscall_timing")]
+use timing;
 
 /// The BRK mutex.
 ///
 /// This is used for avoiding data races in multiple allocator.
 static BRK_MUTEX: Mutex<()> = Mutex::new(());
 
+/// The maximal number of bytes a single SBRK on the current thread can acquire.
+///
+/// This is used to simulate a limited program break in tests.
+#[cfg(test)]
+#[thread_local]
+pub static mut SBRK_LIMIT: usize = !0;
+
+/// The maximal number of bytes to obtain from the OS.
+static HEAP_LIMIT: AtomicUsize = AtomicUsize::new(!0);
+/// The number of bytes obtained from the OS.
+static HEAP_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Subtract `n` from the number of bytes obtained from the OS, stopping at 0.
+///
+/// The program break might be moved back below where it was when ralloc started (e.g. by others
+/// calling SBRK), so the released bytes need not have been counted.
+fn sub_heap_size(n: usize) {
+    let mut old = HEAP_SIZE.load(atomic::Ordering::Relaxed);
+    loop {
+        let res = HEAP_SIZE.compare_and_swap(old, old.saturating_sub(n), atomic::Ordering::Relaxed);
+        if res == old {
+            break;
+        }
+        old = res;
+    }
+}
+
+/// A system call.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Syscall {
+    /// `sbrk`, both growing and shrinking.
+    Sbrk,
+    /// `sched_yield`.
+    SchedYield,
+}
+
+/// Make a system call, `f`, recording how long it takes.
+#[inline]
+#[cfg(feature = "syscall_timing")]
+fn timed<T, F: FnOnce() -> T>(syscall: Syscall, f: F) -> T {
+    let start = shim::monotonic_nanos();
+    let res = f();
+    timing::record(syscall, shim::monotonic_nanos().saturating_sub(start));
+
+    res
+}
+
+/// Make a system call, `f`.
+///
+/// Without the `syscall_timing` feature, nothing is recorded.
+#[inline]
+#[cfg(not(feature = "syscall_timing"))]
+fn timed<T, F: FnOnce() -> T>(_: Syscall, f: F) -> T {
+    f()
+}
+
 /// Increment data segment of this process by some, _n_, return a pointer to the new data segment
 /// start.
 ///
@@ -19,23 +79,116 @@ static BRK_MUTEX: Mutex<()> = Mutex::new(());
 /// # Safety
 ///
 /// This is safe unless you have negative or overflowing `n`.
+///
+/// Growing the data segment fails if it would push the total number of bytes obtained past the
+/// heap limit (see [`set_heap_limit`](fn.set_heap_limit.html)).
 #[inline]
 pub unsafe fn sbrk(n: isize) -> Result<*mut u8, ()> {
+    #[cfg(test)]
+    {
+        if n > 0 && n as usize > SBRK_LIMIT {
+            return Err(());
+        }
+    }
+
     // Lock the BRK mutex.
     #[cfg(not(feature = "unsafe_no_brk_lock"))]
     let _guard = BRK_MUTEX.lock();
 
-    let brk = shim::sbrk(n);
+    // Check the heap limit. This is done under the lock, so no one else grows the heap meanwhile.
+    if n > 0 {
+        let limit = HEAP_LIMIT.load(atomic::Ordering::Relaxed);
+        if HEAP_SIZE.load(atomic::Ordering::Relaxed).checked_add(n as usize).map_or(true, |x| x > limit) {
+            return Err(());
+        }
+    }
+
+    let brk = timed(Syscall::Sbrk, || shim::sbrk(n));
     if brk as usize == !0 {
         Err(())
     } else {
+        if n > 0 {
+            HEAP_SIZE.fetch_add(n as usize, atomic::Ordering::Relaxed);
+        } else {
+            sub_heap_size(n.wrapping_neg() as usize);
+        }
+
         Ok(brk as *mut u8)
     }
 }
 
+/// Shrink the data segment by `n` bytes, if it currently ends at `end`.
+///
+/// The check and the shrinking are done atomically with respect to `sbrk`, so memory acquired by
+/// others in the meantime is never released.
+///
+/// # Safety
+///
+/// The `n` bytes below `end` must be unused.
+#[inline]
+#[allow(cast_possible_wrap)]
+pub unsafe fn shrink_brk(end: *mut u8, n: usize) -> Result<(), ()> {
+    // Lock the BRK mutex.
+    #[cfg(not(feature = "unsafe_no_brk_lock"))]
+    let _guard = BRK_MUTEX.lock();
+
+    if timed(Syscall::Sbrk, || shim::sbrk(0)) as *mut u8 == end
+        && timed(Syscall::Sbrk, || shim::sbrk(-(n as isize))) as usize != !0 {
+        sub_heap_size(n);
+
+        Ok(())
+    } else {
+        Err(())
+    }
+}
+
+/// Set the maximal number of bytes to obtain from the OS.
+///
+/// Once the limit is reached, extending the program break fails, and the allocator goes OOM,
+/// independently of the OS resource limits. The limit covers all memory obtained through `sbrk`,
+/// including the bookkeeping of the allocator, and memory released back to the OS no longer
+/// counts. Setting a limit below the current heap size merely stops it from growing.
+///
+/// There is no limit by default (`!0`).
+#[inline]
+pub fn set_heap_limit(bytes: usize) {
+    HEAP_LIMIT.store(bytes, atomic::Ordering::Relaxed);
+}
+
+/// Get the number of bytes obtained from the OS, and not released yet.
+#[inline]
+pub fn heap_size() -> usize {
+    HEAP_SIZE.load(atomic::Ordering::Relaxed)
+}
+
+/// The cache line size assumed, when the platform does not tell.
+const DEFAULT_CACHE_LINE_SIZE: usize = 64;
+
+/// The cache line size, or 0, if it has not been queried yet.
+static CACHE_LINE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Get the size of a cache line, in bytes.
+///
+/// This is read from the platform where available (and cached), and defaults to 64 bytes.
+#[inline]
+pub fn cache_line_size() -> usize {
+    let size = CACHE_LINE_SIZE.load(atomic::Ordering::Relaxed);
+    if size != 0 {
+        return size;
+    }
+
+    let size = match shim::cache_line_size() {
+        0 => DEFAULT_CACHE_LINE_SIZE,
+        x => x,
+    };
+    CACHE_LINE_SIZE.store(size, atomic::Ordering::Relaxed);
+
+    size
+}
+
 /// Cooperatively gives up a timeslice to the OS scheduler.
 pub fn yield_now() {
-    assert_eq!(unsafe { shim::sched_yield() }, 0);
+    assert_eq!(timed(Syscall::SchedYield, || unsafe { shim::sched_yield() }), 0);
 }
 
 /// Register a thread destructor.
@@ -74,6 +227,14 @@ pub fn log(s: &str) -> Result<(), ()> {
 mod test {
     use super::*;
 
+    #[test]
+    fn test_cache_line_size() {
+        let size = cache_line_size();
+        assert!(size != 0);
+        // The result is cached.
+        assert_eq!(cache_line_size(), size);
+    }
+
     #[test]
     fn test_oom() {
         unsafe {
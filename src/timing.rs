@@ -0,0 +1,121 @@
+//! Syscall timing.
+//!
+//! This keeps the latency statistics of the system calls made through `sys`, which can be dumped
+//! to correlate pauses with heap growth.
+
+use core::fmt;
+use core::sync::atomic::{self, AtomicUsize};
+
+use sys::Syscall;
+
+/// The timing statistics of a system call.
+struct Timing {
+    /// The number of calls.
+    count: AtomicUsize,
+    /// The total time spent, in nanoseconds.
+    total: AtomicUsize,
+    /// The shortest call, in nanoseconds.
+    min: AtomicUsize,
+    /// The longest call, in nanoseconds.
+    max: AtomicUsize,
+}
+
+impl Timing {
+    /// Create empty statistics.
+    const fn new() -> Timing {
+        Timing {
+            count: AtomicUsize::new(0),
+            total: AtomicUsize::new(0),
+            min: AtomicUsize::new(!0),
+            max: AtomicUsize::new(0),
+        }
+    }
+
+    /// Record a call taking `nanos` nanoseconds.
+    fn record(&self, nanos: usize) {
+        self.count.fetch_add(1, atomic::Ordering::Relaxed);
+        self.total.fetch_add(nanos, atomic::Ordering::Relaxed);
+
+        // Update the extrema. Racing updates retry until they don't make it worse.
+        let mut min = self.min.load(atomic::Ordering::Relaxed);
+        while nanos < min {
+            let old = self.min.compare_and_swap(min, nanos, atomic::Ordering::Relaxed);
+            if old == min {
+                break;
+            }
+            min = old;
+        }
+        let mut max = self.max.load(atomic::Ordering::Relaxed);
+        while nanos > max {
+            let old = self.max.compare_and_swap(max, nanos, atomic::Ordering::Relaxed);
+            if old == max {
+                break;
+            }
+            max = old;
+        }
+    }
+}
+
+/// The timing statistics, indexed by the system call.
+static TIMINGS: [Timing; 2] = [Timing::new(), Timing::new()];
+
+/// Get the statistics of a system call.
+fn get(syscall: Syscall) -> &'static Timing {
+    &TIMINGS[syscall as usize]
+}
+
+/// Record a call to `syscall` taking `nanos` nanoseconds.
+#[inline]
+#[allow(cast_possible_truncation)]
+pub fn record(syscall: Syscall, nanos: u64) {
+    get(syscall).record(nanos as usize);
+}
+
+/// Dump the syscall timings.
+///
+/// The count, and the total, minimum, and maximum time (in nanoseconds) of every system call are
+/// written to `out`, one per line.
+pub fn dump_syscall_timings<W: fmt::Write>(out: &mut W) -> fmt::Result {
+    for &syscall in &[Syscall::Sbrk, Syscall::SchedYield] {
+        let timing = get(syscall);
+        let count = timing.count.load(atomic::Ordering::Relaxed);
+
+        if count == 0 {
+            writeln!(out, "{:?}: no calls", syscall)?;
+        } else {
+            writeln!(out, "{:?}: {} calls, {} ns total, {} ns min, {} ns max", syscall, count,
+                     timing.total.load(atomic::Ordering::Relaxed),
+                     timing.min.load(atomic::Ordering::Relaxed),
+                     timing.max.load(atomic::Ordering::Relaxed))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use core::sync::atomic;
+
+    use sys;
+
+    #[test]
+    fn test_sbrk_timing() {
+        let timing = get(Syscall::Sbrk);
+        let count = timing.count.load(atomic::Ordering::Relaxed);
+        let total = timing.total.load(atomic::Ordering::Relaxed);
+
+        for _ in 0..8 {
+            unsafe {
+                sys::sbrk(16).unwrap();
+            }
+        }
+
+        assert!(timing.count.load(atomic::Ordering::Relaxed) >= count + 8);
+        assert!(timing.total.load(atomic::Ordering::Relaxed) > total);
+        assert!(timing.min.load(atomic::Ordering::Relaxed) <=
+                timing.max.load(atomic::Ordering::Relaxed));
+    }
+}
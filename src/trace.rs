@@ -0,0 +1,160 @@
+//! Allocation tracing.
+//!
+//! This keeps the most recent allocation events in a fixed-size ring buffer, which can be dumped
+//! for post-mortem debugging (e.g. when running out of memory).
+
+use core::fmt;
+use core::sync::atomic::{self, AtomicUsize};
+
+/// The number of events kept in the ring buffer.
+const RING_SIZE: usize = 64;
+
+/// An empty slot of the ring buffer.
+const EMPTY_SLOT: Slot = Slot {
+    seq: AtomicUsize::new(0),
+    op: AtomicUsize::new(0),
+    addr: AtomicUsize::new(0),
+    size: AtomicUsize::new(0),
+};
+
+/// The ring buffer of recent events.
+static RING: [Slot; RING_SIZE] = [
+    EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT,
+    EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT,
+    EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT,
+    EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT,
+    EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT,
+    EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT,
+    EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT,
+    EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT, EMPTY_SLOT,
+];
+/// The number of events recorded so far.
+///
+/// This is used as the cursor of the ring buffer.
+static CURSOR: AtomicUsize = AtomicUsize::new(0);
+
+/// The sequence number of a slot being written to.
+const WRITING: usize = !0;
+
+/// An allocation operation.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Op {
+    /// An allocation.
+    Alloc,
+    /// A free.
+    Free,
+    /// A reallocation (the event holds the new buffer).
+    Realloc,
+}
+
+impl Op {
+    /// Decode an operation stored in a slot.
+    fn from_usize(x: usize) -> Op {
+        match x {
+            0 => Op::Alloc,
+            1 => Op::Free,
+            _ => Op::Realloc,
+        }
+    }
+}
+
+/// A slot of the ring buffer, holding an event.
+///
+/// Every field is atomic, so writers and readers racing on a slot never cause undefined behavior.
+/// The slot is guarded by a sequence number (as in a seqlock), with which readers detect and skip
+/// entries being overwritten meanwhile.
+struct Slot {
+    /// The number of the event in this slot plus one, 0 if there is none, or `WRITING`, if the
+    /// slot is being written to.
+    seq: AtomicUsize,
+    /// The operation (see `Op`).
+    op: AtomicUsize,
+    /// The address of the buffer.
+    addr: AtomicUsize,
+    /// The size of the buffer.
+    size: AtomicUsize,
+}
+
+/// Record an event.
+///
+/// Every writer claims its own slot through the cursor, so no lock is needed. Only if more
+/// writers than there are slots race, two of them might share a slot, in which case the slot
+/// holds a mix of their events. This is an acceptable trade-off for a debugging tool.
+#[inline]
+pub fn record(op: Op, addr: *mut u8, size: usize) {
+    let n = CURSOR.fetch_add(1, atomic::Ordering::Relaxed);
+    let slot = &RING[n % RING_SIZE];
+
+    slot.seq.store(WRITING, atomic::Ordering::Relaxed);
+    atomic::fence(atomic::Ordering::Release);
+
+    slot.op.store(op as usize, atomic::Ordering::Relaxed);
+    slot.addr.store(addr as usize, atomic::Ordering::Relaxed);
+    slot.size.store(size, atomic::Ordering::Relaxed);
+
+    slot.seq.store(n.wrapping_add(1), atomic::Ordering::Release);
+}
+
+/// Read event number `n` from the ring buffer.
+///
+/// This returns `None` if the slot holds no event, another event, or is written to meanwhile.
+fn read(n: usize) -> Option<(Op, usize, usize)> {
+    let slot = &RING[n % RING_SIZE];
+
+    if slot.seq.load(atomic::Ordering::Acquire) != n.wrapping_add(1) {
+        return None;
+    }
+
+    let res = (Op::from_usize(slot.op.load(atomic::Ordering::Relaxed)),
+               slot.addr.load(atomic::Ordering::Relaxed),
+               slot.size.load(atomic::Ordering::Relaxed));
+
+    // Make sure the fields were not overwritten while reading them.
+    atomic::fence(atomic::Ordering::Acquire);
+    if slot.seq.load(atomic::Ordering::Relaxed) != n.wrapping_add(1) {
+        return None;
+    }
+
+    Some(res)
+}
+
+/// Dump the most recent allocation events.
+///
+/// The events are written to `out`, one per line, from the oldest to the newest. Events being
+/// recorded concurrently are skipped.
+pub fn dump_recent_events<W: fmt::Write>(out: &mut W) -> fmt::Result {
+    let cursor = CURSOR.load(atomic::Ordering::Relaxed);
+
+    for n in cursor.saturating_sub(RING_SIZE)..cursor {
+        if let Some((op, addr, size)) = read(n) {
+            writeln!(out, "{:?} 0x{:x}[{}]", op, addr, size)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use write::Buffer;
+
+    #[test]
+    fn test_dump() {
+        record(Op::Alloc, 0xdead0 as *mut u8, 8);
+        record(Op::Realloc, 0xdead1 as *mut u8, 16);
+        record(Op::Free, 0xdead2 as *mut u8, 16);
+
+        let mut buffer = Buffer::new();
+        dump_recent_events(&mut buffer).unwrap();
+        let dump = buffer.as_str();
+
+        // Other threads might record events meanwhile, so we only check the relative order.
+        let alloc = dump.find("Alloc 0xdead0[8]").unwrap();
+        let realloc = dump.find("Realloc 0xdead1[16]").unwrap();
+        let free = dump.find("Free 0xdead2[16]").unwrap();
+        assert!(alloc < realloc);
+        assert!(realloc < free);
+    }
+}
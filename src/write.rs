@@ -6,6 +6,8 @@
 use prelude::*;
 
 use core::fmt;
+#[cfg(test)]
+use core::str;
 
 use {sys, sync};
 
@@ -47,6 +49,47 @@ impl fmt::Write for LogWriter {
     }
 }
 
+/// A fixed-size buffer to write into.
+///
+/// This is used by the tests of the writers, which cannot write into a `String`.
+#[cfg(test)]
+pub struct Buffer {
+    /// The buffer.
+    buf: [u8; 4096],
+    /// The number of bytes written.
+    len: usize,
+}
+
+#[cfg(test)]
+impl Buffer {
+    /// Create an empty buffer.
+    pub fn new() -> Buffer {
+        Buffer {
+            buf: [0; 4096],
+            len: 0,
+        }
+    }
+
+    /// Get the text written so far.
+    pub fn as_str(&self) -> &str {
+        str::from_utf8(&self.buf[..self.len]).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl fmt::Write for Buffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.len + s.len() > self.buf.len() {
+            return Err(fmt::Error);
+        }
+
+        self.buf[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+        self.len += s.len();
+
+        Ok(())
+    }
+}
+
 /// Make a runtime assertion.
 ///
 /// The only way it differs from the one provided by `libcore` is the panicking strategy, which
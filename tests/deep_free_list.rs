@@ -0,0 +1,28 @@
+extern crate ralloc;
+
+mod util;
+
+#[test]
+fn deep_free_list() {
+    util::multiply(|| {
+        let mut ptrs = [0 as *mut u8; 1000];
+
+        for i in ptrs.iter_mut() {
+            *i = ralloc::alloc(16, 8);
+        }
+
+        unsafe {
+            // Free every other block, such that none of them can be merged. This makes the block
+            // pool grow repeatedly while it is being written to.
+            for i in 0..500 {
+                util::acid(|| {
+                    ralloc::free(ptrs[2 * i], 16);
+                });
+            }
+
+            for i in 0..500 {
+                ralloc::free(ptrs[2 * i + 1], 16);
+            }
+        }
+    });
+}
@@ -0,0 +1,24 @@
+// The freed block must be scribbled on right away, and be left alone afterwards: The quarantine
+// delays the free, the paranoid canary and the security zeroing overwrite the pattern, and the
+// sanitizer forbids reading the freed block.
+#![cfg(not(any(feature = "quarantine", feature = "paranoid", feature = "security",
+               feature = "sanitizer")))]
+
+extern crate ralloc;
+
+#[test]
+fn free_scribble() {
+    // The pattern is global, so this is the only test in the process.
+    ralloc::set_free_scribble(Some(0xCC));
+
+    unsafe {
+        let ptr = ralloc::alloc(16, 1);
+        ralloc::free(ptr, 16);
+
+        for i in 0..16 {
+            assert_eq!(*ptr.offset(i), 0xCC);
+        }
+    }
+
+    ralloc::set_free_scribble(None);
+}
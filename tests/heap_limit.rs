@@ -0,0 +1,41 @@
+#![cfg(feature = "oom_unwind")]
+
+extern crate ralloc;
+
+use std::panic;
+
+fn panic_handler() -> ! {
+    panic!("Out of memory.");
+}
+
+#[test]
+fn heap_limit() {
+    ralloc::set_oom_handler(panic_handler);
+
+    let limit = ralloc::heap_size() + 16 * 4096;
+    ralloc::set_heap_limit(limit);
+
+    // The program break can be extended up to the limit, but not past it.
+    unsafe {
+        for _ in 0..16 {
+            assert!(ralloc::sbrk(4096).is_ok());
+        }
+        assert!(ralloc::sbrk(1).is_err());
+    }
+    assert_eq!(ralloc::heap_size(), limit);
+
+    // Allocations needing more memory from the OS fail cleanly.
+    assert!(panic::catch_unwind(|| {
+        ralloc::alloc(1 << 20, 1);
+    }).is_err());
+    assert!(ralloc::heap_size() <= limit);
+
+    // Lifting the limit makes memory available again.
+    ralloc::set_heap_limit(!0);
+    unsafe {
+        let ptr = ralloc::alloc(1 << 20, 1);
+        *ptr = 42;
+        assert_eq!(*ptr, 42);
+        ralloc::free(ptr, 1 << 20);
+    }
+}
@@ -0,0 +1,38 @@
+#![cfg(feature = "stats")]
+
+extern crate ralloc;
+
+use std::sync::atomic::{self, AtomicUsize};
+
+/// The threshold, which is well above what the test harness has live.
+const THRESHOLD: usize = 1 << 22;
+
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn callback(live: usize) {
+    assert!(live >= THRESHOLD);
+    CALLS.fetch_add(1, atomic::Ordering::SeqCst);
+}
+
+#[test]
+fn high_water_callback() {
+    // The callback is global, so this is the only test in the process.
+    ralloc::set_high_water_callback(THRESHOLD, callback);
+
+    unsafe {
+        let a = ralloc::alloc(2 * THRESHOLD, 1);
+        assert_eq!(CALLS.load(atomic::Ordering::SeqCst), 1);
+
+        // Staying above the threshold does not call it again.
+        let b = ralloc::alloc(1 << 10, 1);
+        ralloc::free(b, 1 << 10);
+        assert_eq!(CALLS.load(atomic::Ordering::SeqCst), 1);
+
+        // Dropping below rearms it.
+        ralloc::free(a, 2 * THRESHOLD);
+        let a = ralloc::alloc(2 * THRESHOLD, 1);
+        assert_eq!(CALLS.load(atomic::Ordering::SeqCst), 2);
+
+        ralloc::free(a, 2 * THRESHOLD);
+    }
+}
@@ -0,0 +1,59 @@
+#![cfg(feature = "oom_unwind")]
+
+extern crate ralloc;
+
+use std::panic;
+
+fn panic_handler() -> ! {
+    panic!("Out of memory.");
+}
+
+#[test]
+fn recover_from_oom() {
+    ralloc::set_oom_handler(panic_handler);
+
+    // The OOM handler unwinds to this point.
+    assert!(panic::catch_unwind(|| {
+        ralloc::alloc(9999999999999, 1);
+    }).is_err());
+
+    // The allocator should still be usable.
+    unsafe {
+        let ptr = ralloc::alloc(64, 8);
+        *ptr = 42;
+        assert_eq!(*ptr, 42);
+        ralloc::free(ptr, 64);
+    }
+
+    let mut vec = Vec::new();
+    for i in 0..1000 {
+        vec.push(i);
+    }
+    assert_eq!(vec[999], 999);
+}
+
+#[test]
+fn contiguous_overflow() {
+    ralloc::set_oom_handler(panic_handler);
+
+    // The total size of the sub-buffers does not fit the address space.
+    assert!(panic::catch_unwind(|| {
+        let mut ptrs = [0 as *mut u8; 2];
+        ralloc::alloc_contiguous(&[!0 - 4, 16], 8, &mut ptrs);
+    }).is_err());
+    assert!(panic::catch_unwind(|| {
+        let mut ptrs = [0 as *mut u8; 2];
+        ralloc::alloc_contiguous(&[1, !0 - 4], 8, &mut ptrs);
+    }).is_err());
+}
+
+#[test]
+#[cfg(feature = "redzone")]
+fn redzone_overflow() {
+    ralloc::set_oom_handler(panic_handler);
+
+    // The size fits the address space, but not along with the red zone.
+    assert!(panic::catch_unwind(|| {
+        ralloc::alloc(!0 - 3, 1);
+    }).is_err());
+}
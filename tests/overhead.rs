@@ -0,0 +1,35 @@
+// The quarantine would keep the freed blocks out of the pools, which then keep changing.
+#![cfg(all(feature = "stats", not(feature = "quarantine")))]
+
+extern crate ralloc;
+
+/// The metadata kept per live allocation, i.e. the red zone.
+#[cfg(feature = "redzone")]
+const PER_BLOCK: usize = 8;
+/// The metadata kept per live allocation, i.e. nothing.
+#[cfg(not(feature = "redzone"))]
+const PER_BLOCK: usize = 0;
+
+#[test]
+fn overhead_per_block() {
+    let mut ptrs = [0 as *mut u8; 64];
+
+    // Let the pools grow first, so the allocations below are served without changing them.
+    for ptr in ptrs.iter_mut() {
+        *ptr = ralloc::alloc(8, 1);
+    }
+    for &ptr in &ptrs {
+        unsafe { ralloc::free(ptr, 8); }
+    }
+
+    // This is the only test in the process, so nothing else allocates in the meantime.
+    let before = ralloc::overhead_bytes();
+    for ptr in ptrs.iter_mut() {
+        *ptr = ralloc::alloc(8, 1);
+    }
+    assert_eq!(ralloc::overhead_bytes(), before + 64 * PER_BLOCK);
+
+    for &ptr in &ptrs {
+        unsafe { ralloc::free(ptr, 8); }
+    }
+}
@@ -38,3 +38,25 @@ fn realloc_vec_2() {
         }
     });
 }
+
+#[test]
+fn realloc_aligned() {
+    util::multiply(|| {
+        let ptr = ralloc::alloc(16, 256);
+        assert_eq!(ptr as usize % 256, 0);
+
+        unsafe {
+            *ptr = 0xAB;
+
+            // Block the space right to the buffer, so the reallocation has to move it.
+            let blocker = ralloc::alloc(1, 1);
+
+            let ptr = ralloc::realloc(ptr, 16, 100000, 256);
+            assert_eq!(ptr as usize % 256, 0);
+            assert_eq!(*ptr, 0xAB);
+
+            ralloc::free(blocker, 1);
+            ralloc::free(ptr, 100000);
+        }
+    });
+}
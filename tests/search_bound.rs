@@ -0,0 +1,35 @@
+// The small blocks are freed partially, which the red zones do not allow, and the freed blocks
+// must enter the pool right away, rather than the quarantine.
+#![cfg(not(any(feature = "redzone", feature = "quarantine")))]
+
+extern crate ralloc;
+
+#[test]
+fn search_bound() {
+    unsafe {
+        // Leave six small free blocks, separated by live ones, in front of a large free block.
+        let buf = ralloc::alloc(6 * 16 + (1 << 16) + 8, 1);
+        for i in 0..6 {
+            ralloc::free(buf.offset(i * 16), 8);
+        }
+        let large = buf.offset(6 * 16);
+        ralloc::free(large, 1 << 16);
+
+        // The large block is not within the bound, so the allocation is served elsewhere.
+        ralloc::set_search_bound(6);
+        let a = ralloc::alloc(1 << 15, 1);
+        assert!(a != large);
+
+        // Without a bound, the large block is found.
+        ralloc::set_search_bound(!0);
+        let b = ralloc::alloc(1 << 15, 1);
+        assert_eq!(b, large);
+
+        ralloc::free(a, 1 << 15);
+        ralloc::free(b, 1 << 15);
+        for i in 0..6 {
+            ralloc::free(buf.offset(i * 16 + 8), 8);
+        }
+        ralloc::free(buf.offset(6 * 16 + (1 << 16)), 8);
+    }
+}
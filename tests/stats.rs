@@ -0,0 +1,37 @@
+#![cfg(feature = "stats")]
+
+extern crate ralloc;
+
+mod util;
+
+use std::thread;
+
+#[test]
+fn monotonic_counters() {
+    let mut join = Vec::new();
+
+    for _ in 0..4 {
+        join.push(thread::spawn(|| {
+            for i in 0..1000 {
+                util::acid(|| {
+                    let mut vec = vec![0u8; i];
+                    vec.push(1);
+                });
+            }
+        }));
+    }
+
+    let mut last = ralloc::stats();
+    for _ in 0..10000 {
+        let stats = ralloc::stats();
+
+        assert!(stats.splits >= last.splits);
+        assert!(stats.merges >= last.merges);
+
+        last = stats;
+    }
+
+    for i in join {
+        i.join().unwrap();
+    }
+}
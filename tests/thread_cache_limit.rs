@@ -0,0 +1,46 @@
+// The freed buffer must enter the thread cache right away, rather than the quarantine.
+#![cfg(all(feature = "tls", not(feature = "quarantine")))]
+
+extern crate ralloc;
+
+use std::sync::mpsc;
+use std::thread;
+
+/// The size of the buffer, which is larger than any other free block.
+const SIZE: usize = 1 << 20;
+
+#[test]
+fn thread_cache_limit() {
+    // The limit is global, so this is the only test in the process.
+    ralloc::set_thread_cache_limit(64);
+
+    let (freed_tx, freed_rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+
+    // The buffer is freed on another thread, which stays alive, so its cache is not given to the
+    // global allocator at exit.
+    let thread = thread::spawn(move || {
+        unsafe {
+            let ptr = ralloc::alloc(SIZE, 1);
+            ralloc::free(ptr, SIZE);
+            freed_tx.send(ptr as usize).unwrap();
+        }
+
+        done_rx.recv().unwrap();
+    });
+
+    let ptr = freed_rx.recv().unwrap();
+
+    unsafe {
+        // The freed block exceeds the limit, so it should have been moved to the global
+        // allocator, where this thread finds it.
+        let new = ralloc::alloc(SIZE, 1);
+        assert!(new as usize <= ptr && ptr < new as usize + SIZE);
+        ralloc::free(new, SIZE);
+    }
+
+    done_tx.send(()).unwrap();
+    thread.join().unwrap();
+
+    ralloc::set_thread_cache_limit(256 * 1024);
+}
@@ -0,0 +1,30 @@
+// The freed buffer must be reused right away, rather than being held in the quarantine, and the
+// sanitizer forbids writing to it after the free.
+#![cfg(not(any(feature = "quarantine", feature = "sanitizer")))]
+
+extern crate ralloc;
+
+#[test]
+fn zero_on_alloc() {
+    unsafe {
+        let a = ralloc::alloc(4096, 1);
+        for i in 0..4096 {
+            *a.offset(i) = 0xAA;
+        }
+        ralloc::free(a, 4096);
+
+        // The setting is global, so this is the only test in the process.
+        ralloc::set_zero_on_alloc(true);
+        let b = ralloc::alloc(4096, 1);
+        ralloc::set_zero_on_alloc(false);
+
+        // The freed buffer is the only free block this large, so it is reused, but the old
+        // contents are gone.
+        assert_eq!(b, a);
+        for i in 0..4096 {
+            assert_eq!(*b.offset(i), 0);
+        }
+
+        ralloc::free(b, 4096);
+    }
+}